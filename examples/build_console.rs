@@ -5,6 +5,7 @@ use futures::stream::{self};
 use indicatif::ProgressState;
 use indicatif::ProgressStyle;
 use rand::Rng;
+use tracing::Level;
 use tracing::info;
 use tracing::info_span;
 use tracing::instrument;
@@ -80,7 +81,9 @@ async fn main() {
                 }
             },
         ),
-    ).with_span_child_prefix_symbol("↳ ").with_span_child_prefix_indent(" ");
+    ).with_span_child_prefix_symbol("↳ ").with_span_child_prefix_indent(" ")
+    // Show the latest log message of each unit in its progress bar.
+    .with_event_messages(Level::INFO);
 
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer().with_writer(indicatif_layer.get_stderr_writer()))
@@ -102,7 +105,7 @@ async fn main() {
     header_span.pb_set_length(1);
     header_span.pb_set_position(1);
 
-    stream::iter((0..20).map(|val| build(val)))
+    stream::iter((0..20).map(build))
         .buffer_unordered(7)
         .collect::<Vec<()>>()
         .await;
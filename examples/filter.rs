@@ -52,7 +52,7 @@ async fn main() {
         .with(indicatif_layer.with_filter(IndicatifFilter::new(false)))
         .init();
 
-    let res: u64 = stream::iter((0..20).map(|val| do_work(val)))
+    let res: u64 = stream::iter((0..20).map(do_work))
         .buffer_unordered(5)
         .collect::<Vec<u64>>()
         .await
@@ -0,0 +1,125 @@
+//! Progress of parent spans derived from their child spans, see
+//! [`IndicatifLayer::with_child_progress`](crate::IndicatifLayer::with_child_progress).
+use std::collections::HashMap;
+
+use tracing_core::span;
+
+/// Derives the length and position of a progress bar from the progress bars of its child spans,
+/// e.g. a `build` span spawning 5 `build_sub_unit` spans shows `3/5` once 3 of them have closed.
+///
+/// Child spans count towards their closest parent span that has a progress bar, from the moment
+/// they are created, if that parent derives its progress from its children by then. Each child
+/// span counts as many times as its weight, see
+/// [`IndicatifSpanExt::pb_set_weight`](crate::span_ext::IndicatifSpanExt::pb_set_weight). Children
+/// of children count towards their own parent, which in turn reports its derived progress to its
+/// parent if that tracks its children too.
+///
+/// A span deriving its progress from its children should not set its own length or position, as
+/// they are overwritten whenever one of its children progresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChildProgress {
+    /// The length is the number of child spans, the position the number of child spans that have
+    /// closed.
+    Count,
+    /// The length is the sum of the lengths of the child progress bars, the position the sum of
+    /// their positions. Closed child spans count as complete, child progress bars without a
+    /// length do not count.
+    Length,
+}
+
+/// What a child span reports to its parent, see [`ChildrenProgress::update`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ChildState {
+    pub(crate) len: Option<u64>,
+    pub(crate) pos: u64,
+    pub(crate) weight: u64,
+    pub(crate) closed: bool,
+}
+
+impl ChildState {
+    fn progress(&self) -> Progress {
+        let weight = self.weight;
+        let (len, pos) = match self.len {
+            Some(len) if self.closed => (len, len),
+            Some(len) => (len, self.pos.min(len)),
+            None => (0, 0),
+        };
+
+        Progress {
+            count_len: weight,
+            count_pos: if self.closed { weight } else { 0 },
+            len: len.saturating_mul(weight),
+            pos: pos.saturating_mul(weight),
+        }
+    }
+}
+
+/// The progress of the children of a span, tracked for both [`ChildProgress`] modes so the mode
+/// can change at any time.
+#[derive(Debug, Default)]
+pub(crate) struct ChildrenProgress {
+    // The latest progress of children that have not closed yet. Closed children are only kept in
+    // `total`, as span ids may be reused.
+    open: HashMap<span::Id, Progress>,
+    total: Progress,
+    has_children: bool,
+}
+
+impl ChildrenProgress {
+    pub(crate) fn update(&mut self, child: &span::Id, state: &ChildState) {
+        let progress = state.progress();
+        let previous = if state.closed {
+            self.open.remove(child)
+        } else {
+            self.open.insert(child.clone(), progress)
+        };
+
+        if let Some(previous) = previous {
+            self.total = self.total.sub(previous);
+        }
+
+        self.total = self.total.add(progress);
+        self.has_children = true;
+    }
+
+    /// Returns the derived length and position, or `None` if there are no children yet.
+    pub(crate) fn length_and_position(&self, mode: ChildProgress) -> Option<(u64, u64)> {
+        if !self.has_children {
+            return None;
+        }
+
+        Some(match mode {
+            ChildProgress::Count => (self.total.count_len, self.total.count_pos),
+            ChildProgress::Length => (self.total.len, self.total.pos),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Progress {
+    count_len: u64,
+    count_pos: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Progress {
+    fn add(self, other: Self) -> Self {
+        Self {
+            count_len: self.count_len.saturating_add(other.count_len),
+            count_pos: self.count_pos.saturating_add(other.count_pos),
+            len: self.len.saturating_add(other.len),
+            pos: self.pos.saturating_add(other.pos),
+        }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            count_len: self.count_len.saturating_sub(other.count_len),
+            count_pos: self.count_pos.saturating_sub(other.count_pos),
+            len: self.len.saturating_sub(other.len),
+            pos: self.pos.saturating_sub(other.pos),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Pluggable time source for progress bars, see
+//! [`IndicatifLayer::with_clock`](crate::IndicatifLayer::with_clock).
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use indicatif::FormattedDuration;
+use indicatif::HumanDuration;
+use indicatif::ProgressState;
+use indicatif::style::ProgressStyle;
+use indicatif::style::ProgressTracker;
+
+/// A source of time used to compute when progress bars started, how long they have been running
+/// and their ETA.
+///
+/// Only the difference between two returned [`Instant`]s is ever used, so implementations are free
+/// to return instants that do not follow the wall clock, e.g. to replay a recorded run or to make
+/// tests deterministic.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] following the system's monotonic clock, i.e. [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Tracks the elapsed time and ETA of a single progress bar using a [`Clock`].
+#[derive(Clone)]
+pub(crate) struct SpanTimer {
+    clock: Arc<dyn Clock>,
+    // Shared with the style keys of the progress bar, so resets are reflected there too.
+    started: Arc<Mutex<Instant>>,
+}
+
+impl SpanTimer {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        let started = Arc::new(Mutex::new(clock.now()));
+
+        Self { clock, started }
+    }
+
+    pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub(crate) fn reset(&self) {
+        if let Ok(mut started) = self.started.lock() {
+            *started = self.clock.now();
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        match self.started.lock() {
+            Ok(started) => self.clock.now().saturating_duration_since(*started),
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn eta(&self, pos: u64, len: Option<u64>) -> Duration {
+        let Some(len) = len else {
+            return Duration::ZERO;
+        };
+
+        if pos == 0 || pos >= len {
+            return Duration::ZERO;
+        }
+
+        // A simple linear estimate. Unlike indicatif's estimator, this only depends on the clock
+        // and the current position, so it is reproducible.
+        self.elapsed().mul_f64((len - pos) as f64 / pos as f64)
+    }
+
+    /// Overrides indicatif's time based template keys with ones computed from this timer.
+    pub(crate) fn add_keys_to_style(&self, style: ProgressStyle) -> ProgressStyle {
+        style
+            .with_key("elapsed", self.key(TimerKey::Elapsed))
+            .with_key("elapsed_precise", self.key(TimerKey::ElapsedPrecise))
+            .with_key("eta", self.key(TimerKey::Eta))
+            .with_key("eta_precise", self.key(TimerKey::EtaPrecise))
+            .with_key("duration", self.key(TimerKey::Duration))
+            .with_key("duration_precise", self.key(TimerKey::DurationPrecise))
+    }
+
+    fn key(&self, kind: TimerKey) -> SpanTimerKey {
+        SpanTimerKey {
+            timer: self.clone(),
+            kind,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TimerKey {
+    Elapsed,
+    ElapsedPrecise,
+    Eta,
+    EtaPrecise,
+    Duration,
+    DurationPrecise,
+}
+
+#[derive(Clone)]
+struct SpanTimerKey {
+    timer: SpanTimer,
+    kind: TimerKey,
+}
+
+impl ProgressTracker for SpanTimerKey {
+    fn clone_box(&self) -> Box<dyn ProgressTracker> {
+        Box::new(self.clone())
+    }
+
+    fn tick(&mut self, _: &ProgressState, _: Instant) {}
+
+    fn reset(&mut self, _: &ProgressState, _: Instant) {}
+
+    fn write(&self, state: &ProgressState, w: &mut dyn std::fmt::Write) {
+        let elapsed = self.timer.elapsed();
+        let eta = self.timer.eta(state.pos(), state.len());
+
+        let _ = match self.kind {
+            TimerKey::Elapsed => write!(w, "{:#}", HumanDuration(elapsed)),
+            TimerKey::ElapsedPrecise => write!(w, "{}", FormattedDuration(elapsed)),
+            TimerKey::Eta => write!(w, "{:#}", HumanDuration(eta)),
+            TimerKey::EtaPrecise => write!(w, "{}", FormattedDuration(eta)),
+            TimerKey::Duration => write!(w, "{:#}", HumanDuration(elapsed + eta)),
+            TimerKey::DurationPrecise => write!(w, "{}", FormattedDuration(elapsed + eta)),
+        };
+    }
+}
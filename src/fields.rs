@@ -0,0 +1,302 @@
+//! Reserved span and event fields that drive progress bars through plain `tracing`, without
+//! depending on [`IndicatifSpanExt`](crate::span_ext::IndicatifSpanExt).
+use std::fmt;
+
+use tracing::field::DisplayValue;
+use tracing::field::Visit;
+use tracing_core::Field;
+use tracing_core::Metadata;
+use tracing_core::field::FieldSet;
+use tracing_core::field::Value;
+use tracing_core::span;
+
+use crate::IndicatifSpanContext;
+
+/// Sets the length of the progress bar, see
+/// [`pb_set_length`](crate::span_ext::IndicatifSpanExt::pb_set_length).
+pub(crate) const PB_LEN: &str = "indicatif.pb_len";
+/// Sets the position of the progress bar, see
+/// [`pb_set_position`](crate::span_ext::IndicatifSpanExt::pb_set_position).
+pub(crate) const PB_POS: &str = "indicatif.pb_pos";
+/// Increments the position of the progress bar, see
+/// [`pb_inc`](crate::span_ext::IndicatifSpanExt::pb_inc).
+pub(crate) const PB_INC: &str = "indicatif.pb_inc";
+/// Sets the message of the progress bar, see
+/// [`pb_set_message`](crate::span_ext::IndicatifSpanExt::pb_set_message).
+pub(crate) const PB_MSG: &str = "indicatif.pb_msg";
+/// Sets the message of the progress bar once it finishes, see
+/// [`pb_set_finish_message`](crate::span_ext::IndicatifSpanExt::pb_set_finish_message).
+pub(crate) const PB_FINISH_MSG: &str = "indicatif.pb_finish_msg";
+/// Sets how many times the span counts towards the progress of its parent, see
+/// [`pb_set_weight`](crate::span_ext::IndicatifSpanExt::pb_set_weight).
+pub(crate) const PB_WEIGHT: &str = "indicatif.pb_weight";
+/// Sets the priority of the progress bar, see
+/// [`pb_set_priority`](crate::span_ext::IndicatifSpanExt::pb_set_priority).
+pub(crate) const PB_PRIORITY: &str = "indicatif.pb_priority";
+/// Pins the progress bar, see
+/// [`pb_set_pinned`](crate::span_ext::IndicatifSpanExt::pb_set_pinned).
+pub(crate) const PB_PINNED: &str = "indicatif.pb_pinned";
+
+/// Selects the style of the progress bar, see
+/// [`IndicatifLayer::with_style_rule`](crate::IndicatifLayer::with_style_rule).
+pub(crate) const PB_STYLE: &str = "indicatif.pb_style";
+
+/// All fields that report progress, which should not be shown as regular fields.
+pub(crate) const PROGRESS_FIELDS: [&str; 8] = [
+    PB_LEN,
+    PB_POS,
+    PB_INC,
+    PB_MSG,
+    PB_FINISH_MSG,
+    PB_WEIGHT,
+    PB_PRIORITY,
+    PB_PINNED,
+];
+
+/// Returns whether the given span or event has any field that controls progress bars.
+pub(crate) fn has_progress_fields(meta: &Metadata<'_>) -> bool {
+    PROGRESS_FIELDS
+        .iter()
+        .any(|name| meta.fields().field(name).is_some())
+}
+
+/// Collects the values of reserved progress fields.
+#[derive(Default)]
+pub(crate) struct ProgressFields {
+    len: Option<u64>,
+    pos: Option<u64>,
+    inc: Option<u64>,
+    msg: Option<String>,
+    finish_msg: Option<String>,
+    weight: Option<u64>,
+    priority: Option<i64>,
+    pinned: Option<bool>,
+}
+
+impl ProgressFields {
+    /// Applies the collected values to the progress bar of a span, in the order length, position,
+    /// increment, message, finish message, weight, priority and pinning.
+    pub(crate) fn apply(self, indicatif_ctx: &mut IndicatifSpanContext) {
+        if let Some(len) = self.len {
+            indicatif_ctx.set_progress_bar_length(len);
+        }
+
+        if let Some(pos) = self.pos {
+            indicatif_ctx.set_progress_bar_position(pos);
+        }
+
+        if let Some(inc) = self.inc {
+            indicatif_ctx.inc_progress_bar_position(inc);
+        }
+
+        if let Some(msg) = self.msg {
+            indicatif_ctx.set_progress_bar_message(msg);
+        }
+
+        if let Some(finish_msg) = self.finish_msg {
+            indicatif_ctx.set_progress_bar_finish_message(finish_msg);
+        }
+
+        if let Some(weight) = self.weight {
+            indicatif_ctx.set_progress_bar_weight(weight);
+        }
+
+        if let Some(priority) = self.priority {
+            indicatif_ctx.set_progress_bar_priority(priority);
+        }
+
+        if let Some(pinned) = self.pinned {
+            indicatif_ctx.set_progress_bar_pinned(pinned);
+        }
+    }
+}
+
+impl Visit for ProgressFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            PB_LEN => self.len = Some(value),
+            PB_POS => self.pos = Some(value),
+            PB_INC => self.inc = Some(value),
+            PB_WEIGHT => self.weight = Some(value),
+            PB_PRIORITY => self.priority = i64::try_from(value).ok(),
+            _ => {}
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        // The priority may be negative.
+        if field.name() == PB_PRIORITY {
+            self.priority = Some(value);
+            return;
+        }
+
+        // Integer literals are recorded as `i64`, e.g. `indicatif.pb_len = 10`.
+        if let Ok(value) = u64::try_from(value) {
+            self.record_u64(field, value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == PB_PINNED {
+            self.pinned = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            PB_MSG => self.msg = Some(value.to_string()),
+            PB_FINISH_MSG => self.finish_msg = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            PB_MSG => self.msg = Some(format!("{value:?}")),
+            PB_FINISH_MSG => self.finish_msg = Some(format!("{value:?}")),
+            _ => {}
+        }
+    }
+}
+
+/// Collects the recorded values of a span that are not [`PROGRESS_FIELDS`], so they can be merged
+/// into its formatted fields without the progress fields, whatever the formatter.
+#[derive(Default)]
+pub(crate) struct RecordedFields {
+    values: Vec<(Field, RecordedValue)>,
+    has_progress_fields: bool,
+}
+
+enum RecordedValue {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+    // Recorded as `fmt::Debug`, kept formatted.
+    Debug(DisplayValue<String>),
+}
+
+impl RecordedValue {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            Self::I64(value) => value,
+            Self::U64(value) => value,
+            Self::F64(value) => value,
+            Self::Bool(value) => value,
+            Self::Str(value) => value,
+            Self::Debug(value) => value,
+        }
+    }
+}
+
+impl RecordedFields {
+    /// Calls `f` with `values`, leaving out the progress fields. `fields` are the fields of the
+    /// span the values were recorded for. `f` is not called if there are no other values.
+    pub(crate) fn without_progress_fields(
+        values: &span::Record<'_>,
+        fields: &FieldSet,
+        f: impl FnOnce(&span::Record<'_>),
+    ) {
+        let mut recorded = Self::default();
+        values.record(&mut recorded);
+
+        if !recorded.has_progress_fields {
+            f(values);
+            return;
+        }
+
+        let Some((first_field, _)) = recorded.values.first() else {
+            return;
+        };
+
+        // A span has at most 32 fields, see `tracing_core::field::FieldSet`. Unused entries have no
+        // value, so they are skipped.
+        let mut entries: [(&Field, Option<&dyn Value>); 32] = [(first_field, None); 32];
+
+        for (entry, (field, value)) in entries.iter_mut().zip(&recorded.values) {
+            *entry = (field, Some(value.as_value()));
+        }
+
+        f(&span::Record::new(&fields.value_set(&entries)));
+    }
+
+    fn push(&mut self, field: &Field, value: RecordedValue) {
+        if PROGRESS_FIELDS.contains(&field.name()) {
+            self.has_progress_fields = true;
+        } else {
+            self.values.push((field.clone(), value));
+        }
+    }
+}
+
+impl Visit for RecordedFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, RecordedValue::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, RecordedValue::U64(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, RecordedValue::F64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, RecordedValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, RecordedValue::Str(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(
+            field,
+            RecordedValue::Debug(tracing::field::display(format!("{value:?}"))),
+        );
+    }
+}
+
+/// Extracts the value of the [`PB_STYLE`] field of a span.
+#[derive(Default)]
+pub(crate) struct StyleField {
+    pub(crate) style: Option<String>,
+}
+
+impl Visit for StyleField {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == PB_STYLE {
+            self.style = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == PB_STYLE {
+            self.style = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Extracts the message of an event, i.e. its `message` field.
+#[derive(Default)]
+pub(crate) struct EventMessage {
+    pub(crate) message: Option<String>,
+}
+
+impl Visit for EventMessage {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // The message of an event is recorded as `fmt::Arguments`, whose `Debug` output is not
+        // quoted.
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        }
+    }
+}
@@ -23,27 +23,50 @@
 use std::fmt;
 use std::marker::PhantomData;
 
+use tracing::field::Visit;
 use tracing_core::Field;
 use tracing_core::Subscriber;
+use tracing_core::span;
 use tracing_subscriber::field::MakeVisitor;
 use tracing_subscriber::field::VisitFmt;
 use tracing_subscriber::field::VisitOutput;
+use tracing_subscriber::filter::FilterFn;
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::layer::Context;
 use tracing_subscriber::layer::Filter;
+use tracing_subscriber::registry::LookupSpan;
 
+use crate::fields::PB_STYLE;
+use crate::fields::PROGRESS_FIELDS;
+use crate::fields::has_progress_fields;
 use crate::util::FilteredFormatFields;
 
+mod directives;
+
+pub use directives::Directives;
+pub use directives::ParseError;
+
 /// A filter that filters based on the presence of a field with the name of either
 /// "indicatif.pb_show" or "indicatif.pb_hide" on the span.
 ///
-/// The value for this field is irrelevant and not factored in to the filtering (this is due to
-/// tracing not making field values available in the `on_new_span` method). To avoid confusion, it
-/// is recommended to set the value of this field to [`tracing::field::Empty`].
+/// By default, the value for this field is irrelevant and not factored in to the filtering (this is
+/// due to tracing not making field values available in the `enabled` method). To avoid confusion,
+/// it is recommended to set the value of this field to [`tracing::field::Empty`]. See
+/// [`IndicatifFilter::with_field_values`] to take boolean values into account.
 ///
 /// If both "indicatif.pb_show" and "indicatif.pb_hide" are present, the behavior is to show a
-/// progress bar.
+/// progress bar. Spans without either field can be matched by [`Directives`], see
+/// [`IndicatifFilter::with_directives`].
+///
+/// Events are only passed through if they report progress (see
+/// [`IndicatifLayer`](crate::IndicatifLayer) for the fields that do), see
+/// [`IndicatifFilter::with_event_level`] to pass through more events.
 pub struct IndicatifFilter<S> {
     show_progress_bars_by_default: bool,
+    read_field_values: bool,
+    directives: Option<Directives>,
+    event_level: Option<tracing::Level>,
     subscriber: PhantomData<S>,
 }
 
@@ -57,19 +80,86 @@ impl<S: Subscriber> IndicatifFilter<S> {
     pub fn new(show_progress_bars_by_default: bool) -> Self {
         Self {
             show_progress_bars_by_default,
+            read_field_values: false,
+            directives: None,
+            event_level: None,
             subscriber: PhantomData,
         }
     }
+
+    /// Takes boolean values of "indicatif.pb_show" and "indicatif.pb_hide" into account, so
+    /// progress bars can be shown or hidden based on runtime decisions, e.g.
+    /// `info_span!("download", indicatif.pb_show = verbose)`.
+    ///
+    /// "indicatif.pb_show = false" hides the progress bar and "indicatif.pb_hide = false" shows it.
+    /// If "indicatif.pb_show" is present, its value decides. Fields with a value that is not a
+    /// boolean (including [`tracing::field::Empty`]) behave as if values were not taken into
+    /// account. Only the values the span is created with are considered.
+    ///
+    /// Child spans of a span whose progress bar is hidden this way are shown as children of the
+    /// closest parent span that has a progress bar, and events report progress to that span as
+    /// well.
+    pub fn with_field_values(mut self, read_field_values: bool) -> Self {
+        self.read_field_values = read_field_values;
+        self
+    }
+
+    /// Decides whether spans get a progress bar based on [`Directives`], e.g. parsed from the
+    /// `RUST_PROGRESS` environment variable.
+    ///
+    /// "indicatif.pb_show" and "indicatif.pb_hide" take precedence over directives, and
+    /// `show_progress_bars_by_default` is used for spans that no directive matches.
+    pub fn with_directives(mut self, directives: Directives) -> Self {
+        self.directives = Some(directives);
+        self
+    }
+
+    /// Passes through events at `level` or more severe, in addition to events reporting progress,
+    /// e.g. for [`IndicatifLayer::with_event_messages`](crate::IndicatifLayer::with_event_messages)
+    /// or [`IndicatifLayer::with_log_tail`](crate::IndicatifLayer::with_log_tail).
+    pub fn with_event_level(mut self, level: tracing::Level) -> Self {
+        self.event_level = Some(level);
+        self
+    }
+}
+
+/// Marks a span whose progress bar was hidden by [`IndicatifFilter`] based on a field value, so
+/// [`IndicatifLayer`](crate::IndicatifLayer) does not create one.
+pub(crate) struct HiddenProgressBar;
+
+// Collects the boolean values of "indicatif.pb_show" and "indicatif.pb_hide".
+#[derive(Default)]
+struct ShowHideValues {
+    show: Option<bool>,
+    hide: Option<bool>,
+}
+
+impl Visit for ShowHideValues {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        match field.name() {
+            "indicatif.pb_show" => self.show = Some(value),
+            "indicatif.pb_hide" => self.hide = Some(value),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn fmt::Debug) {}
 }
 
-impl<S: Subscriber> Filter<S> for IndicatifFilter<S> {
+impl<S> Filter<S> for IndicatifFilter<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
     fn enabled(
         &self,
         meta: &tracing::Metadata<'_>,
         _: &tracing_subscriber::layer::Context<'_, S>,
     ) -> bool {
         if !meta.is_span() {
-            return false;
+            // Events may still report progress to their spans.
+            return meta.is_event()
+                && (has_progress_fields(meta)
+                    || self.event_level.is_some_and(|level| *meta.level() <= level));
         }
 
         if meta.fields().field("indicatif.pb_show").is_some() {
@@ -77,14 +167,46 @@ impl<S: Subscriber> Filter<S> for IndicatifFilter<S> {
         }
 
         if meta.fields().field("indicatif.pb_hide").is_some() {
-            return false;
+            // The value is only known once the span is created, see `on_new_span`.
+            return self.read_field_values;
         }
 
-        self.show_progress_bars_by_default
+        self.directives
+            .as_ref()
+            .and_then(|directives| directives.show_progress_bar(meta))
+            .unwrap_or(self.show_progress_bars_by_default)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !self.read_field_values {
+            return;
+        }
+
+        let mut values = ShowHideValues::default();
+        attrs.record(&mut values);
+
+        let fields = attrs.metadata().fields();
+        let show = if fields.field("indicatif.pb_show").is_some() {
+            values.show.unwrap_or(true)
+        } else if fields.field("indicatif.pb_hide").is_some() {
+            !values.hide.unwrap_or(true)
+        } else {
+            return;
+        };
+
+        if !show {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(HiddenProgressBar);
+            }
+        }
     }
 }
 
-/// Returns a [`tracing_subscriber::fmt::FormatFields`] that ignores the "indicatif.pb_show" and "indicatif.pb_hide" fields.
+/// Returns a [`tracing_subscriber::fmt::FormatFields`] that ignores the "indicatif.pb_show",
+/// "indicatif.pb_hide" and "indicatif.pb_style" fields, as well as the fields reporting progress
+/// ("indicatif.pb_len", "indicatif.pb_pos", "indicatif.pb_inc", "indicatif.pb_msg",
+/// "indicatif.pb_finish_msg", "indicatif.pb_weight", "indicatif.pb_priority" and
+/// "indicatif.pb_pinned").
 pub fn hide_indicatif_span_fields<'writer, Format>(
     format: Format,
 ) -> FilteredFormatFields<Format, impl Fn(&Field) -> bool + Clone>
@@ -93,6 +215,36 @@ where
     Format::Visitor: VisitFmt + VisitOutput<fmt::Result>,
 {
     FilteredFormatFields::new(format, |field: &Field| {
-        field.name() != "indicatif.pb_show" && field.name() != "indicatif.pb_hide"
+        field.name() != "indicatif.pb_show"
+            && field.name() != "indicatif.pb_hide"
+            && field.name() != PB_STYLE
+            && !PROGRESS_FIELDS.contains(&field.name())
     })
 }
+
+/// Returns a filter that drops events reporting progress (i.e. carrying "indicatif.pb_inc",
+/// "indicatif.pb_pos", "indicatif.pb_len", "indicatif.pb_msg", "indicatif.pb_finish_msg",
+/// "indicatif.pb_weight", "indicatif.pb_priority" or "indicatif.pb_pinned" fields), e.g. to keep
+/// them out of the logs of a `fmt` layer.
+///
+/// ```
+/// use tracing_indicatif::IndicatifLayer;
+/// use tracing_indicatif::filter::hide_indicatif_progress_events;
+/// use tracing_subscriber::layer::Layer;
+/// use tracing_subscriber::layer::SubscriberExt;
+/// use tracing_subscriber::util::SubscriberInitExt;
+///
+/// let indicatif_layer = IndicatifLayer::new();
+///
+/// tracing_subscriber::registry()
+///     .with(
+///         tracing_subscriber::fmt::layer()
+///             .with_writer(indicatif_layer.get_stderr_writer())
+///             .with_filter(hide_indicatif_progress_events()),
+///     )
+///     .with(indicatif_layer)
+///     .init();
+/// ```
+pub fn hide_indicatif_progress_events() -> FilterFn<impl Fn(&tracing::Metadata<'_>) -> bool> {
+    filter_fn(|meta| !(meta.is_event() && has_progress_fields(meta)))
+}
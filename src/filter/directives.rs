@@ -0,0 +1,227 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use tracing_core::Metadata;
+use tracing_subscriber::filter::LevelFilter;
+
+/// Directives choosing which spans get progress bars, in a syntax modeled on
+/// [`EnvFilter`](https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html),
+/// e.g. `RUST_PROGRESS=myapp::net=info,myapp::db[query]=off`.
+///
+/// Directives are separated by commas and have the form `target[span_name]=level`, where every
+/// part is optional:
+/// * `target` matches spans whose target starts with it, e.g. `myapp::net`
+/// * `span_name` matches spans with exactly this name, e.g. `[query]`
+/// * `level` is one of `off`, `error`, `warn`, `info`, `debug` or `trace` (the default if
+///   omitted), spans at this level or more severe get a progress bar
+///
+/// A directive with only a level (e.g. `info`) applies to all spans. If several directives match
+/// a span, the one with the longest target wins, then the one with a span name, then the later
+/// one. Unlike `EnvFilter`, `[span_name]` only matches the span itself, not spans inside of it, and
+/// field filters (`[span_name{field=value}]`) are not supported.
+///
+/// Use with [`IndicatifFilter::with_directives`](super::IndicatifFilter::with_directives), e.g.
+/// ```
+/// use tracing_indicatif::IndicatifLayer;
+/// use tracing_indicatif::filter::Directives;
+/// use tracing_indicatif::filter::IndicatifFilter;
+/// use tracing_subscriber::layer::Layer;
+/// use tracing_subscriber::layer::SubscriberExt;
+/// use tracing_subscriber::util::SubscriberInitExt;
+///
+/// let indicatif_layer = IndicatifLayer::new();
+///
+/// tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer().with_writer(indicatif_layer.get_stderr_writer()))
+///     .with(indicatif_layer.with_filter(
+///         IndicatifFilter::new(true).with_directives(Directives::from_default_env()),
+///     ))
+///     .init();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Directives {
+    directives: Vec<Directive>,
+}
+
+#[derive(Clone, Debug)]
+struct Directive {
+    target: Option<String>,
+    span_name: Option<String>,
+    level: LevelFilter,
+}
+
+impl Directives {
+    /// The environment variable read by [`Self::from_default_env`].
+    pub const DEFAULT_ENV: &'static str = "RUST_PROGRESS";
+
+    /// Parses directives from a string, ignoring any invalid directives.
+    pub fn new(directives: impl AsRef<str>) -> Self {
+        Self {
+            directives: split_directives(directives.as_ref())
+                .filter_map(|directive| Directive::parse(directive).ok())
+                .collect(),
+        }
+    }
+
+    /// Parses directives from a string, returning an error if any directive is invalid.
+    pub fn try_new(directives: impl AsRef<str>) -> Result<Self, ParseError> {
+        Ok(Self {
+            directives: split_directives(directives.as_ref())
+                .map(Directive::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Parses directives from the [`Self::DEFAULT_ENV`] environment variable, ignoring any
+    /// invalid directives.
+    pub fn from_default_env() -> Self {
+        Self::from_env(Self::DEFAULT_ENV)
+    }
+
+    /// Parses directives from the given environment variable, ignoring any invalid directives.
+    ///
+    /// There are no directives if the environment variable is not set.
+    pub fn from_env(env: impl AsRef<str>) -> Self {
+        env::var(env.as_ref()).map(Self::new).unwrap_or_default()
+    }
+
+    /// Parses directives from the given environment variable, returning an error if it is not set
+    /// or any directive is invalid.
+    pub fn try_from_env(env: impl AsRef<str>) -> Result<Self, ParseError> {
+        let directives = env::var(env.as_ref()).map_err(|err| ParseError {
+            kind: ParseErrorKind::Env(err),
+        })?;
+
+        Self::try_new(directives)
+    }
+
+    /// Returns whether the given span should get a progress bar according to the most specific
+    /// matching directive, or `None` if no directive matches.
+    pub(crate) fn show_progress_bar(&self, meta: &Metadata<'_>) -> Option<bool> {
+        self.directives
+            .iter()
+            .filter(|directive| directive.matches(meta))
+            .max_by_key(|directive| {
+                (
+                    directive.target.as_ref().map_or(0, String::len),
+                    directive.span_name.is_some(),
+                )
+            })
+            .map(|directive| *meta.level() <= directive.level)
+    }
+}
+
+impl FromStr for Directives {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_new(s)
+    }
+}
+
+fn split_directives(directives: &str) -> impl Iterator<Item = &str> {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+}
+
+impl Directive {
+    fn parse(directive: &str) -> Result<Self, ParseError> {
+        let invalid = |reason| ParseError {
+            kind: ParseErrorKind::InvalidDirective {
+                directive: directive.to_string(),
+                reason,
+            },
+        };
+
+        let (selector, level) = match directive.rsplit_once('=') {
+            Some((selector, level)) => (
+                selector,
+                level
+                    .trim()
+                    .parse::<LevelFilter>()
+                    .map_err(|_| invalid("invalid level"))?,
+            ),
+            // A lone level applies to all spans.
+            None => match directive.parse::<LevelFilter>() {
+                Ok(level) => ("", level),
+                Err(_) => (directive, LevelFilter::TRACE),
+            },
+        };
+
+        let (target, span_name) = match selector.split_once('[') {
+            Some((target, span_name)) => {
+                let span_name = span_name
+                    .strip_suffix(']')
+                    .ok_or_else(|| invalid("missing closing `]`"))?;
+
+                if span_name.contains('{') {
+                    return Err(invalid("field filters are not supported"));
+                }
+
+                (target, Some(span_name))
+            }
+            None => (selector, None),
+        };
+
+        Ok(Self {
+            target: Some(target.trim())
+                .filter(|target| !target.is_empty())
+                .map(str::to_string),
+            span_name: span_name
+                .map(str::trim)
+                .filter(|span_name| !span_name.is_empty())
+                .map(str::to_string),
+            level,
+        })
+    }
+
+    fn matches(&self, meta: &Metadata<'_>) -> bool {
+        self.target
+            .as_ref()
+            .is_none_or(|target| meta.target().starts_with(target.as_str()))
+            && self
+                .span_name
+                .as_ref()
+                .is_none_or(|span_name| meta.name() == span_name)
+    }
+}
+
+/// Returned if [`Directives`] could not be parsed.
+#[derive(Debug)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+enum ParseErrorKind {
+    InvalidDirective {
+        directive: String,
+        reason: &'static str,
+    },
+    Env(env::VarError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::InvalidDirective {
+                ref directive,
+                reason,
+            } => write!(f, "invalid directive `{directive}`: {reason}"),
+            ParseErrorKind::Env(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ParseErrorKind::InvalidDirective { .. } => None,
+            ParseErrorKind::Env(ref err) => Some(err),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! A handle to reconfigure an [`IndicatifLayer`](crate::IndicatifLayer) at runtime.
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::style::ProgressStyle;
+
+use crate::TickSettings;
+use crate::pb_manager::ProgressBarManager;
+
+/// A handle to an [`IndicatifLayer`](crate::IndicatifLayer) that allows changing its settings
+/// after the layer has been registered with a subscriber.
+///
+/// Obtained via [`IndicatifLayer::get_handle`](crate::IndicatifLayer::get_handle) or
+/// [`get_indicatif_handle`]. This is merely a wrapper around an `Arc`, so it is cheap to clone.
+///
+/// Changes apply to progress bars that are already shown or queued, unless noted otherwise.
+#[derive(Clone)]
+pub struct IndicatifHandle {
+    pb_manager: Arc<Mutex<ProgressBarManager>>,
+}
+
+impl IndicatifHandle {
+    pub(crate) fn new(pb_manager: Arc<Mutex<ProgressBarManager>>) -> Self {
+        Self { pb_manager }
+    }
+
+    /// Changes the default style of progress bars, see
+    /// [`IndicatifLayer::with_progress_style`](crate::IndicatifLayer::with_progress_style).
+    ///
+    /// Progress bars with a style set through
+    /// [`pb_set_style`](crate::span_ext::IndicatifSpanExt::pb_set_style) keep that style.
+    pub fn set_progress_style(&self, style: ProgressStyle) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.update_style_settings(|style_settings| {
+                style_settings.progress_style = style;
+            });
+        }
+    }
+
+    /// Changes the indent used to mark the "level" of a child span's progress bar, see
+    /// [`IndicatifLayer::with_span_child_prefix_indent`](crate::IndicatifLayer::with_span_child_prefix_indent).
+    pub fn set_span_child_prefix_indent(&self, indent: &'static str) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.update_style_settings(|style_settings| {
+                style_settings.span_child_prefix_indent = indent;
+            });
+        }
+    }
+
+    /// Changes the symbol used to denote a progress bar from a child span, see
+    /// [`IndicatifLayer::with_span_child_prefix_symbol`](crate::IndicatifLayer::with_span_child_prefix_symbol).
+    pub fn set_span_child_prefix_symbol(&self, symbol: &'static str) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.update_style_settings(|style_settings| {
+                style_settings.span_child_prefix_symbol = symbol;
+            });
+        }
+    }
+
+    /// Changes the maximum number of progress bars that will be displayed and the footer, see
+    /// [`IndicatifLayer::with_max_progress_bars`](crate::IndicatifLayer::with_max_progress_bars).
+    ///
+    /// If the maximum is increased, queued progress bars are shown right away. If it is decreased,
+    /// progress bars that are already shown stay shown until their span closes.
+    pub fn set_max_progress_bars(
+        &self,
+        max_progress_bars: u64,
+        footer_style: Option<ProgressStyle>,
+    ) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_max_progress_bars(max_progress_bars, footer_style);
+            pb_manager.show_pending_progress_bars();
+        }
+    }
+
+    /// Changes how long progress bars are shown before making room for pending ones, see
+    /// [`IndicatifLayer::with_rotation`](crate::IndicatifLayer::with_rotation). `None` turns
+    /// rotation off.
+    pub fn set_rotation(&self, rotation_interval: Option<Duration>) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_rotation_interval(rotation_interval);
+            pb_manager.ensure_background_ticker(Arc::downgrade(&self.pb_manager));
+        }
+    }
+
+    /// Stops drawing progress bars until [`Self::enable_rendering`] is called, see
+    /// [`disable_tracing_indicatif`](crate::disable_tracing_indicatif).
+    pub fn disable_rendering(&self) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_rendering_enabled(false);
+        }
+    }
+
+    /// Draws progress bars again after [`Self::disable_rendering`] was called, see
+    /// [`enable_tracing_indicatif`](crate::enable_tracing_indicatif).
+    pub fn enable_rendering(&self) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_rendering_enabled(true);
+        }
+    }
+
+    /// Returns whether progress bars are currently drawn, i.e. rendering has not been disabled
+    /// through [`Self::disable_rendering`].
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.pb_manager.lock().is_ok_and(|pb_manager| {
+            pb_manager
+                .rendering_enabled
+                .load(std::sync::atomic::Ordering::Acquire)
+        })
+    }
+
+    /// Changes how often progress bars are recalculated and redrawn to the terminal, see
+    /// [`IndicatifLayer::with_tick_settings`](crate::IndicatifLayer::with_tick_settings).
+    pub fn set_tick_settings(&self, tick_settings: TickSettings) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_tick_settings(tick_settings);
+        }
+    }
+}
+
+/// Returns the handle (equivalent to
+/// [`get_handle`](crate::IndicatifLayer::get_handle)) of the registered
+/// [`IndicatifLayer`](crate::IndicatifLayer) for the current default tracing subscriber.
+///
+/// Returns `None` if there is either no default tracing subscriber or if there is not a
+/// `IndicatifLayer` registered with that subscriber.
+pub fn get_indicatif_handle() -> Option<IndicatifHandle> {
+    tracing::dispatcher::get_default(|dispatch| {
+        dispatch
+            .downcast_ref::<crate::WithHandle>()
+            .and_then(|ctx| {
+                let mut ret: Option<IndicatifHandle> = None;
+                ctx.with_context(dispatch, |handle| {
+                    ret = Some(handle);
+                });
+
+                ret
+            })
+    })
+}
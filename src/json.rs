@@ -0,0 +1,141 @@
+//! Machine readable progress events, written as newline delimited JSON.
+use std::fmt::Write as _;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use indicatif::ProgressBar;
+use tracing_core::span;
+
+/// The kinds of events emitted by [`JsonEvents`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum JsonEvent {
+    /// The span was entered for the first time and its progress bar was created.
+    Created,
+    /// The progress bar was shown.
+    Shown,
+    /// The progress bar was queued, as the maximum number of progress bars are already shown.
+    Pending,
+    PositionChanged,
+    LengthChanged,
+    MessageChanged,
+    /// The span closed and its progress bar was finished with a message.
+    Finished,
+    /// The span closed and its progress bar was cleared.
+    Cleared,
+}
+
+impl JsonEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            JsonEvent::Created => "created",
+            JsonEvent::Shown => "shown",
+            JsonEvent::Pending => "pending",
+            JsonEvent::PositionChanged => "position",
+            JsonEvent::LengthChanged => "length",
+            JsonEvent::MessageChanged => "message",
+            JsonEvent::Finished => "finished",
+            JsonEvent::Cleared => "cleared",
+        }
+    }
+}
+
+/// Identifies the span a [`JsonEvent`] is about.
+pub(crate) struct JsonSpan<'a> {
+    pub(crate) id: &'a span::Id,
+    pub(crate) parent_id: Option<&'a span::Id>,
+    pub(crate) name: &'a str,
+    pub(crate) fields: &'a str,
+    pub(crate) elapsed: Duration,
+}
+
+/// Writes [`JsonEvent`]s to an [`io::Write`], one JSON object per line.
+pub(crate) struct JsonEvents {
+    writer: Mutex<Box<dyn io::Write + Send>>,
+}
+
+impl JsonEvents {
+    pub(crate) fn new(writer: Box<dyn io::Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub(crate) fn emit(&self, event: JsonEvent, span: JsonSpan<'_>, pb: &ProgressBar) {
+        let line = format_event(event, &span, pb);
+
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.write_all(line.as_bytes());
+            let _ = writer.flush();
+        }
+    }
+}
+
+fn format_event(event: JsonEvent, span: &JsonSpan<'_>, pb: &ProgressBar) -> String {
+    let timestamp_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let mut line = String::new();
+    let _ = write!(
+        line,
+        r#"{{"event":"{}","timestamp_ms":{},"span_id":{},"parent_span_id":"#,
+        event.as_str(),
+        timestamp_ms,
+        span.id.into_u64()
+    );
+
+    match span.parent_id {
+        Some(parent_id) => {
+            let _ = write!(line, "{}", parent_id.into_u64());
+        }
+        None => line.push_str("null"),
+    }
+
+    line.push_str(r#","name":"#);
+    write_json_str(&mut line, span.name);
+    line.push_str(r#","fields":"#);
+    write_json_str(&mut line, span.fields);
+
+    let _ = write!(
+        line,
+        r#","elapsed_ms":{},"pos":{},"len":"#,
+        span.elapsed.as_millis(),
+        pb.position()
+    );
+
+    match pb.length() {
+        Some(len) => {
+            let _ = write!(line, "{len}");
+        }
+        None => line.push_str("null"),
+    }
+
+    line.push_str(r#","message":"#);
+    write_json_str(&mut line, &pb.message());
+    line.push_str("}\n");
+
+    line
+}
+
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
@@ -37,8 +37,12 @@
 //! `indicatif_layer.get_stdout_writer()` to your `fmt::layer()` (depending on where you want to
 //! emit tracing logs) to prevent progress bars from clobbering any console logs.
 use std::any::TypeId;
+use std::collections::VecDeque;
 use std::marker::PhantomData;
+use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
 use std::time::Duration;
 
 use indicatif::MultiProgress;
@@ -55,14 +59,48 @@ use tracing_subscriber::fmt::format::DefaultFields;
 use tracing_subscriber::layer;
 use tracing_subscriber::registry::LookupSpan;
 
+use crate::child_progress::ChildState;
+use crate::child_progress::ChildrenProgress;
+use crate::clock::SpanTimer;
+use crate::fields::EventMessage;
+use crate::fields::ProgressFields;
+use crate::fields::RecordedFields;
+use crate::json::JsonEvent;
+use crate::json::JsonEvents;
+use crate::json::JsonSpan;
+use crate::order::FieldValue;
+use crate::order::OrderEntry;
+use crate::order::OrderField;
+use crate::text::TextProgress;
+
+mod child_progress;
+mod clock;
+mod fields;
 pub mod filter;
+pub mod handle;
+mod json;
+mod order;
 mod pb_manager;
 pub mod span_ext;
+mod style_rules;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod text;
 pub mod util;
 pub mod writer;
 
+pub use child_progress::ChildProgress;
+pub use clock::Clock;
+pub use clock::SystemClock;
+#[doc(inline)]
+pub use handle::IndicatifHandle;
+pub use order::ProgressBarOrder;
+pub use pb_manager::DrawTarget;
+pub use pb_manager::OutputMode;
 use pb_manager::ProgressBarManager;
+use pb_manager::StyleSettings;
 pub use pb_manager::TickSettings;
+pub use style_rules::StyleMatcher;
 #[doc(inline)]
 pub use writer::IndicatifWriter;
 
@@ -125,6 +163,9 @@ pub(crate) struct WithStdoutWriter(
 #[allow(clippy::type_complexity)]
 pub(crate) struct WithMultiProgress(fn(&tracing::Dispatch, f: &mut dyn FnMut(MultiProgress)));
 
+#[allow(clippy::type_complexity)]
+pub(crate) struct WithHandle(fn(&tracing::Dispatch, f: &mut dyn FnMut(IndicatifHandle)));
+
 impl WithContext {
     pub(crate) fn with_context(
         &self,
@@ -166,42 +207,150 @@ impl WithMultiProgress {
     }
 }
 
+impl WithHandle {
+    pub(crate) fn with_context(
+        &self,
+        dispatch: &tracing::Dispatch,
+        mut f: impl FnMut(IndicatifHandle),
+    ) {
+        (self.0)(dispatch, &mut f)
+    }
+}
+
+/// Builds the progress bar message from an event, see
+/// [`IndicatifLayer::with_event_message_formatter`].
+pub type EventMessageFormatter = Box<dyn Fn(&tracing::Event<'_>) -> String + Send + Sync>;
+
+/// Computes the priority of the progress bar of a span from its metadata and depth (the number of
+/// parent spans with progress bars), see [`IndicatifLayer::with_priority`].
+pub type PriorityFn = Box<dyn Fn(&tracing::Metadata<'_>, usize) -> i64 + Send + Sync>;
+
+/// Decides from its metadata whether the progress bar of a span is pinned, see
+/// [`IndicatifLayer::with_pinned`].
+pub type PinnedFn = Box<dyn Fn(&tracing::Metadata<'_>) -> bool + Send + Sync>;
+
+// Settings for `IndicatifLayer::with_event_messages`.
+#[derive(Default)]
+struct EventMessages {
+    // Only `Some` if event messages are shown.
+    level: Option<tracing::Level>,
+    formatter: Option<EventMessageFormatter>,
+}
+
+impl EventMessages {
+    fn message(&self, event: &tracing::Event<'_>) -> Option<String> {
+        if *event.metadata().level() > self.level? {
+            return None;
+        }
+
+        let message = match self.formatter {
+            Some(ref formatter) => formatter(event),
+            None => {
+                let mut message = EventMessage::default();
+                event.record(&mut message);
+                message.message?
+            }
+        };
+
+        (!message.is_empty()).then_some(message)
+    }
+}
+
+// Settings for `IndicatifLayer::with_log_tail`.
+struct LogTail {
+    level: tracing::Level,
+    max_lines: usize,
+}
+
+impl LogTail {
+    fn line(&self, event: &tracing::Event<'_>) -> Option<String> {
+        if *event.metadata().level() > self.level {
+            return None;
+        }
+
+        let mut message = EventMessage::default();
+        event.record(&mut message);
+
+        message.message.filter(|message| !message.is_empty())
+    }
+}
+
 #[derive(Default)]
 struct ProgressBarInitSettings {
-    style: Option<ProgressStyle>,
     len: Option<u64>,
     pos: Option<u64>,
     message: Option<String>,
 }
 
 struct IndicatifSpanContext {
-    // If this progress bar is `Some(pb)` and `pb.is_hidden`, it means the progress bar is queued.
-    // We start the progress bar in hidden mode so things like `elapsed` are accurate.
+    // If this progress bar is `Some(pb)` and the span is not tracked as visible by the
+    // `ProgressBarManager`, it means the progress bar is queued. We start the progress bar in
+    // hidden mode so things like `elapsed` are accurate.
     //
     // If this progress bar is `None`, it means the span has not yet been entered.
     progress_bar: Option<ProgressBar>,
-    // If `Some`, the progress bar will use this style when the span is entered for the first time.
     pb_init_settings: ProgressBarInitSettings,
-    // Notes:
-    // * A parent span cannot close before its child spans, so if a parent span has a progress bar,
-    //   that parent progress bar's lifetime will be greater than this span's progress bar.
-    // * The ProgressBar is just a wrapper around `Arc`, so cloning and tracking it here is fine.
-    parent_progress_bar: Option<ProgressBar>,
+    // If `Some`, the progress bar uses this style instead of the default style of the layer.
+    custom_style: Option<ProgressStyle>,
     // This is only `Some` if we have some parent with a progress bar.
     parent_span: Option<span::Id>,
     // Fields to be passed to the progress bar as keys.
     span_fields_formatted: Option<String>,
     span_name: String,
+    // Computed from `level` when the progress bar is created, as the prefix settings may change at
+    // runtime.
     span_child_prefix: String,
     // Used to quickly compute a child span's prefix without having to traverse up the entire span
     // scope.
     level: u16,
     // If `Some`, this is the message that will be displayed when the progress bar is finished.
     finish_message: Option<String>,
+    // Only `Some` if progress is reported as plain text, see `OutputMode::Text`.
+    text_progress: Option<TextProgress>,
+    // Only `Some` if progress events are written as JSON, see `IndicatifLayer::with_json_events`.
+    json_events: Option<Arc<JsonEvents>>,
+    span_id: span::Id,
+    // Only `Some` if a clock was set with `IndicatifLayer::with_clock`, otherwise we rely on
+    // indicatif's own time keeping.
+    timer: Option<SpanTimer>,
+    // The latest lines logged inside this span, see `IndicatifLayer::with_log_tail`.
+    log_tail: VecDeque<String>,
+    // Whether an error was logged inside this span, in which case its log tail is printed once the
+    // span closes, see `IndicatifLayer::with_log_tail_flush`.
+    failed: bool,
+    // If `Some`, overrides the show delay of the layer, see `IndicatifLayer::with_show_delay`.
+    show_delay: Option<Duration>,
+    // The number of this span in the order spans were created.
+    created: u64,
+    // Only `Some` if progress bars are sorted by a field this span has, see
+    // `ProgressBarOrder::Field`.
+    order_field: Option<FieldValue>,
+    // The position of this span relative to its parents, set when the progress bar is started.
+    order_path: Vec<OrderEntry>,
+    // How many times this span counts towards the progress of its parent, see `ChildProgress`.
+    weight: u64,
+    // If `Some`, the progress of this span is derived from `children`.
+    child_progress: Option<ChildProgress>,
+    children: ChildrenProgress,
+    // Whether the parent of this span derived its progress from its children when this span was
+    // created, in which case this span reports its progress to it.
+    reports_to_parent: bool,
+    // What this span last reported to its parent, so unchanged progress is not reported again.
+    reported_state: Option<ChildState>,
+    // Pending progress bars with a higher priority are shown first, see
+    // `IndicatifLayer::with_priority`.
+    priority: i64,
+    // Pinned progress bars are always shown, see `IndicatifLayer::with_pinned`.
+    pinned: bool,
 }
 
 impl IndicatifSpanContext {
     fn add_keys_to_style(&self, style: ProgressStyle) -> ProgressStyle {
+        let style = match self.timer {
+            Some(ref timer) => timer.add_keys_to_style(style),
+            None => style,
+        };
+
         style
             .with_key(
                 "span_name",
@@ -223,13 +372,66 @@ impl IndicatifSpanContext {
             )
     }
 
-    fn make_progress_bar(&mut self, default_style: &ProgressStyle) {
+    // The label used to identify this span in plain text progress lines and heartbeats.
+    fn text_label(&self) -> String {
+        format!(
+            "{}{{{}}}",
+            self.span_name,
+            self.span_fields_formatted.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn report_progress(&mut self, event: JsonEvent) {
+        let label = self.text_label();
+
+        if let Some(ref pb) = self.progress_bar {
+            let elapsed = self.elapsed_of(pb);
+
+            if let Some(ref mut text_progress) = self.text_progress {
+                text_progress.progressed(&label, pb, elapsed);
+            }
+
+            self.report_json_event(event, pb);
+        }
+    }
+
+    fn report_json_event(&self, event: JsonEvent, pb: &ProgressBar) {
+        if let Some(ref json_events) = self.json_events {
+            json_events.emit(
+                event,
+                JsonSpan {
+                    id: &self.span_id,
+                    parent_id: self.parent_span.as_ref(),
+                    name: &self.span_name,
+                    fields: self.span_fields_formatted.as_deref().unwrap_or_default(),
+                    elapsed: self.elapsed_of(pb),
+                },
+                pb,
+            );
+        }
+    }
+
+    fn report_text_finished(&mut self, pb: &ProgressBar) {
+        let label = self.text_label();
+
+        let elapsed = self.elapsed_of(pb);
+
+        if let Some(ref mut text_progress) = self.text_progress {
+            text_progress.finished(&label, elapsed, self.finish_message.as_deref());
+        }
+    }
+
+    fn make_progress_bar(&mut self, pb_manager: &ProgressBarManager) {
         if self.progress_bar.is_none() {
+            let style_settings = &pb_manager.style_settings;
+            self.span_child_prefix = style_settings.span_child_prefix(self.level);
+
             let pb = ProgressBar::hidden().with_style(
-                self.pb_init_settings
-                    .style
-                    .take()
-                    .unwrap_or_else(|| self.add_keys_to_style(default_style.clone())),
+                self.add_keys_to_style(
+                    self.custom_style
+                        .clone()
+                        .unwrap_or_else(|| style_settings.progress_style.clone()),
+                ),
             );
 
             if let Some(len) = self.pb_init_settings.len.take() {
@@ -244,21 +446,65 @@ impl IndicatifSpanContext {
                 pb.set_position(pos);
             }
 
+            // The progress bar starts now, not when the span was created.
+            if let Some(ref timer) = self.timer {
+                timer.reset();
+            }
+
             self.progress_bar = Some(pb);
         }
     }
 
+    // Starts reporting progress as text and JSON once the progress bar is eligible to be shown,
+    // so spans closing within their show delay leave no trace (see
+    // `IndicatifLayer::with_show_delay`).
+    fn start_reporting(&mut self, pb_manager: &ProgressBarManager) {
+        let Some(ref pb) = self.progress_bar else {
+            return;
+        };
+
+        if let Some(text_output) = pb_manager.text_output() {
+            let clock = self
+                .timer
+                .as_ref()
+                .map_or_else(|| Arc::new(SystemClock) as Arc<dyn Clock>, SpanTimer::clock);
+            let mut text_progress = TextProgress::new(text_output, clock);
+            text_progress.started(&self.text_label());
+            self.text_progress = Some(text_progress);
+        }
+
+        self.json_events = pb_manager.json_events();
+        self.report_json_event(JsonEvent::Created, pb);
+    }
+
     fn set_progress_bar_style(&mut self, style: ProgressStyle) {
         if let Some(ref pb) = self.progress_bar {
-            pb.set_style(self.add_keys_to_style(style));
-        } else {
-            self.pb_init_settings.style = Some(self.add_keys_to_style(style));
+            pb.set_style(self.add_keys_to_style(style.clone()));
+        }
+
+        self.custom_style = Some(style);
+    }
+
+    // Restyles an existing progress bar after the style settings of the layer changed.
+    fn apply_style_settings(&mut self, style_settings: &StyleSettings) {
+        if let Some(ref pb) = self.progress_bar {
+            self.span_child_prefix = style_settings.span_child_prefix(self.level);
+
+            pb.set_style(
+                self.add_keys_to_style(
+                    self.custom_style
+                        .clone()
+                        .unwrap_or_else(|| style_settings.progress_style.clone()),
+                ),
+            );
+            pb.tick();
         }
     }
 
     fn set_progress_bar_length(&mut self, len: u64) {
         if let Some(ref pb) = self.progress_bar {
             pb.set_length(len);
+            self.report_progress(JsonEvent::LengthChanged);
         } else {
             self.pb_init_settings.len = Some(len);
         }
@@ -267,6 +513,7 @@ impl IndicatifSpanContext {
     fn set_progress_bar_position(&mut self, pos: u64) {
         if let Some(ref pb) = self.progress_bar {
             pb.set_position(pos);
+            self.report_progress(JsonEvent::PositionChanged);
         } else {
             self.pb_init_settings.pos = Some(pos);
         }
@@ -275,6 +522,7 @@ impl IndicatifSpanContext {
     fn set_progress_bar_message(&mut self, msg: String) {
         if let Some(ref pb) = self.progress_bar {
             pb.set_message(msg);
+            self.report_progress(JsonEvent::MessageChanged);
         } else {
             self.pb_init_settings.message = Some(msg);
         }
@@ -283,6 +531,7 @@ impl IndicatifSpanContext {
     fn inc_progress_bar_position(&mut self, pos: u64) {
         if let Some(ref pb) = self.progress_bar {
             pb.inc(pos);
+            self.report_progress(JsonEvent::PositionChanged);
         } else if let Some(ref mut pb_pos) = self.pb_init_settings.pos {
             *pb_pos += pos;
         } else {
@@ -294,6 +543,7 @@ impl IndicatifSpanContext {
     fn inc_progress_bar_length(&mut self, len: u64) {
         if let Some(ref pb) = self.progress_bar {
             pb.inc_length(len);
+            self.report_progress(JsonEvent::LengthChanged);
         } else if let Some(ref mut pb_len) = self.pb_init_settings.len {
             *pb_len += len;
         }
@@ -308,12 +558,20 @@ impl IndicatifSpanContext {
     fn reset_progress_bar(&mut self) {
         if let Some(ref pb) = self.progress_bar {
             pb.reset();
+
+            if let Some(ref timer) = self.timer {
+                timer.reset();
+            }
         }
     }
 
     fn reset_progress_bar_elapsed(&mut self) {
         if let Some(ref pb) = self.progress_bar {
             pb.reset_elapsed();
+
+            if let Some(ref timer) = self.timer {
+                timer.reset();
+            }
         }
     }
 
@@ -327,21 +585,139 @@ impl IndicatifSpanContext {
         self.finish_message = Some(msg);
     }
 
+    fn set_progress_bar_show_delay(&mut self, show_delay: Duration) {
+        self.show_delay = Some(show_delay);
+    }
+
+    fn set_progress_bar_priority(&mut self, priority: i64) {
+        self.priority = priority;
+    }
+
+    fn set_progress_bar_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+    }
+
+    fn set_progress_bar_weight(&mut self, weight: u64) {
+        self.weight = weight;
+    }
+
+    fn set_child_progress(&mut self, child_progress: ChildProgress) {
+        self.child_progress = Some(child_progress);
+        self.apply_child_progress();
+    }
+
+    // What this span reports to its parent, see `ChildProgress`.
+    fn child_state(&self, closed: bool) -> ChildState {
+        let (len, pos) = match self.progress_bar {
+            Some(ref pb) => (pb.length(), pb.position()),
+            None => (
+                self.pb_init_settings.len,
+                self.pb_init_settings.pos.unwrap_or_default(),
+            ),
+        };
+
+        ChildState {
+            len,
+            pos,
+            weight: self.weight,
+            closed,
+        }
+    }
+
+    // Returns what to report to the parent, if anything changed since it was last reported.
+    fn state_to_report(&mut self, closed: bool) -> Option<ChildState> {
+        if !self.reports_to_parent {
+            return None;
+        }
+
+        let state = self.child_state(closed);
+        if self.reported_state == Some(state) {
+            return None;
+        }
+
+        self.reported_state = Some(state);
+
+        Some(state)
+    }
+
+    // Returns whether the progress of this span changed, i.e. it derives its progress from its
+    // children.
+    fn update_child_progress(&mut self, child: &span::Id, state: &ChildState) -> bool {
+        if self.child_progress.is_none() {
+            return false;
+        }
+
+        self.children.update(child, state);
+        self.apply_child_progress()
+    }
+
+    fn apply_child_progress(&mut self) -> bool {
+        let Some((len, pos)) = self
+            .child_progress
+            .and_then(|child_progress| self.children.length_and_position(child_progress))
+        else {
+            return false;
+        };
+
+        self.set_progress_bar_length(len);
+        self.set_progress_bar_position(pos);
+
+        true
+    }
+
     fn eta(&self) -> Duration {
-        if let Some(ref pb) = self.progress_bar {
-            pb.eta()
-        } else {
-            Duration::new(0, 0)
+        match (&self.progress_bar, &self.timer) {
+            (Some(pb), Some(timer)) => timer.eta(pb.position(), pb.length()),
+            (Some(pb), None) => pb.eta(),
+            (None, _) => Duration::new(0, 0),
         }
     }
 
     fn elapsed(&self) -> Duration {
         if let Some(ref pb) = self.progress_bar {
-            pb.elapsed()
+            self.elapsed_of(pb)
         } else {
             Duration::new(0, 0)
         }
     }
+
+    // `pb` may have already been taken out of `self.progress_bar` when the span closes.
+    fn elapsed_of(&self, pb: &ProgressBar) -> Duration {
+        self.timer
+            .as_ref()
+            .map_or_else(|| pb.elapsed(), SpanTimer::elapsed)
+    }
+}
+
+/// Reports the progress of a span to its parents, up to the first one that does not derive its
+/// progress from its children, see `ChildProgress`. Nothing is reported if the progress did not
+/// change since it was last reported. `with_span_ctx` must call the given function with the
+/// context of the given span, if that span still exists.
+fn report_child_progress(
+    indicatif_ctx: &mut IndicatifSpanContext,
+    closed: bool,
+    mut with_span_ctx: impl FnMut(&span::Id, &mut dyn FnMut(&mut IndicatifSpanContext)),
+) {
+    let Some(mut state) = indicatif_ctx.state_to_report(closed) else {
+        return;
+    };
+    let mut child_span = indicatif_ctx.span_id.clone();
+    let mut parent_span = indicatif_ctx.parent_span.clone();
+
+    while let Some(span_id) = parent_span.take() {
+        with_span_ctx(&span_id, &mut |parent_ctx| {
+            if !parent_ctx.update_child_progress(&child_span, &state) {
+                return;
+            }
+
+            if let Some(parent_state) = parent_ctx.state_to_report(false) {
+                state = parent_state;
+                parent_span = parent_ctx.parent_span.clone();
+            }
+        });
+
+        child_span = span_id;
+    }
 }
 
 /// The layer that handles creating and managing indicatif progress bars for active spans. This
@@ -357,23 +733,68 @@ impl IndicatifSpanContext {
 /// or when one of its child spans is entered for the first time, and will finish when the span
 /// is [closed](tracing_subscriber::Layer::on_close) (including all child spans having closed).
 ///
-/// Progress bars are emitted to stderr.
+/// Progress bars are emitted to stderr by default, see [`IndicatifLayer::with_draw_target`]. If
+/// the draw target is not a terminal (e.g. stderr is piped to a file), progress is printed as plain
+/// text lines instead, see [`IndicatifLayer::with_output_mode`].
+///
+/// Besides [`span_ext::IndicatifSpanExt`], progress can be reported through the following span
+/// fields, so libraries can report progress without depending on this crate:
+/// * `indicatif.pb_len` - sets the length of the progress bar
+/// * `indicatif.pb_pos` - sets the position of the progress bar
+/// * `indicatif.pb_inc` - increments the position of the progress bar
+/// * `indicatif.pb_msg` - sets the message of the progress bar
+/// * `indicatif.pb_finish_msg` - sets the message of the progress bar once it finishes
+/// * `indicatif.pb_weight` - sets how many times the span counts towards the progress of its
+///   parent, see [`IndicatifLayer::with_child_progress`]
+/// * `indicatif.pb_priority` - sets the priority of the progress bar, see
+///   [`IndicatifLayer::with_priority`]
+/// * `indicatif.pb_pinned` - pins the progress bar, see [`IndicatifLayer::with_pinned`]
+///
+/// The `indicatif.pb_style` field selects the style of a progress bar, see
+/// [`IndicatifLayer::with_style_rule`].
+///
+/// These are applied when the span is created and whenever they are recorded. Events carrying
+/// these fields update the progress bar of the closest span (the event's span or one of its
+/// parents) that has one, e.g.
+/// ```
+/// let span = tracing::info_span!("download", indicatif.pb_len = 100, indicatif.pb_inc = tracing::field::Empty);
+/// span.record("indicatif.pb_inc", 10);
+///
+/// let _enter = span.enter();
+/// tracing::info!(indicatif.pb_inc = 10, "downloaded chunk");
+/// ```
+/// Use [`filter::hide_indicatif_span_fields`] to hide these fields from `{span_fields}` and
+/// other formatted output, and [`filter::hide_indicatif_progress_events`] to hide such events
+/// from your logs.
 ///
 /// Under the hood, this just uses indicatif's [`MultiProgress`] struct to
 /// manage individual [`ProgressBar`] instances per span.
 pub struct IndicatifLayer<S, F = DefaultFields> {
-    pb_manager: Mutex<ProgressBarManager>,
+    pb_manager: Arc<Mutex<ProgressBarManager>>,
     // Allows us to fetch the `MultiProgress` without taking a lock.
     // Do not mutate `mp` directly, always go through `pb_manager`.
     mp: MultiProgress,
+    // Likewise, allows our writers to check whether rendering is enabled without taking a lock.
+    rendering_enabled: Arc<AtomicBool>,
+    // Used to number spans in the order they were created, without taking a lock.
+    created_spans: AtomicU64,
+    // Only `Some` if progress bars are sorted by this field, see `ProgressBarOrder::Field`. Kept
+    // here so creating spans does not take a lock.
+    order_field: Option<&'static str>,
     span_field_formatter: F,
-    progress_style: ProgressStyle,
-    span_child_prefix_indent: &'static str,
-    span_child_prefix_symbol: &'static str,
+    clock: Option<Arc<dyn Clock>>,
+    event_messages: EventMessages,
+    log_tail: Option<LogTail>,
+    log_tail_flush: bool,
+    style_rules: Vec<(StyleMatcher, ProgressStyle)>,
+    child_progress: Option<ChildProgress>,
+    priority: Option<PriorityFn>,
+    pinned: Option<PinnedFn>,
     get_context: WithContext,
     get_stderr_writer_context: WithStderrWriter,
     get_stdout_writer_context: WithStdoutWriter,
     get_multi_progress_context: WithMultiProgress,
+    get_handle_context: WithHandle,
     inner: PhantomData<S>,
 }
 
@@ -416,21 +837,28 @@ where
             TickSettings::default(),
         );
         let mp = pb_manager.mp.clone();
+        let rendering_enabled = pb_manager.rendering_enabled.clone();
 
         Self {
-            pb_manager: Mutex::new(pb_manager),
+            pb_manager: Arc::new(Mutex::new(pb_manager)),
             mp,
+            rendering_enabled,
+            created_spans: AtomicU64::new(0),
+            order_field: None,
             span_field_formatter: DefaultFields::new(),
-            progress_style: ProgressStyle::with_template(
-                "{span_child_prefix}{spinner} {span_name}{{{span_fields}}}",
-            )
-            .expect("valid template"),
-            span_child_prefix_indent: "  ",
-            span_child_prefix_symbol: "↳ ",
+            clock: None,
+            event_messages: EventMessages::default(),
+            log_tail: None,
+            log_tail_flush: true,
+            style_rules: Vec::new(),
+            child_progress: None,
+            priority: None,
+            pinned: None,
             get_context: WithContext(Self::get_context),
             get_stderr_writer_context: WithStderrWriter(Self::get_stderr_writer_context),
             get_stdout_writer_context: WithStdoutWriter(Self::get_stdout_writer_context),
             get_multi_progress_context: WithMultiProgress(Self::get_multi_progress_context),
+            get_handle_context: WithHandle(Self::get_handle_context),
             inner: PhantomData,
         }
     }
@@ -456,7 +884,7 @@ where
     /// [`fmt::Layer::with_writer`](tracing_subscriber::fmt::Layer::with_writer).
     pub fn get_stderr_writer(&self) -> IndicatifWriter<writer::Stderr> {
         // `MultiProgress` is merely a wrapper over an `Arc`, so we can clone here.
-        IndicatifWriter::new(self.mp.clone())
+        IndicatifWriter::with_rendering_enabled(self.mp.clone(), self.rendering_enabled.clone())
     }
 
     /// Returns the a writer for [`std::io::Stdout`] that ensures its output will not be clobbered by
@@ -469,7 +897,16 @@ where
     /// [`fmt::Layer::with_writer`](tracing_subscriber::fmt::Layer::with_writer).
     pub fn get_stdout_writer(&self) -> IndicatifWriter<writer::Stdout> {
         // `MultiProgress` is merely a wrapper over an `Arc`, so we can clone here.
-        IndicatifWriter::new(self.mp.clone())
+        IndicatifWriter::with_rendering_enabled(self.mp.clone(), self.rendering_enabled.clone())
+    }
+
+    /// Returns a handle that can be used to reconfigure this layer after it has been registered with
+    /// a subscriber, e.g. to switch to a more compact progress bar style.
+    ///
+    /// See also [`handle::get_indicatif_handle`] to fetch the handle of the layer registered with
+    /// the current default subscriber.
+    pub fn get_handle(&self) -> IndicatifHandle {
+        IndicatifHandle::new(self.pb_manager.clone())
     }
 
     /// Set the formatter for span fields, the result of which will be available as the
@@ -483,10 +920,18 @@ where
         IndicatifLayer {
             pb_manager: self.pb_manager,
             mp: self.mp,
+            rendering_enabled: self.rendering_enabled,
+            created_spans: self.created_spans,
+            order_field: self.order_field,
             span_field_formatter: formatter,
-            progress_style: self.progress_style,
-            span_child_prefix_indent: self.span_child_prefix_indent,
-            span_child_prefix_symbol: self.span_child_prefix_symbol,
+            clock: self.clock,
+            event_messages: self.event_messages,
+            log_tail: self.log_tail,
+            log_tail_flush: self.log_tail_flush,
+            style_rules: self.style_rules,
+            child_progress: self.child_progress,
+            priority: self.priority,
+            pinned: self.pinned,
             get_context: WithContext(IndicatifLayer::<S, F2>::get_context),
             get_stderr_writer_context: WithStderrWriter(
                 IndicatifLayer::<S, F2>::get_stderr_writer_context,
@@ -497,6 +942,7 @@ where
             get_multi_progress_context: WithMultiProgress(
                 IndicatifLayer::<S, F2>::get_multi_progress_context,
             ),
+            get_handle_context: WithHandle(IndicatifLayer::<S, F2>::get_handle_context),
             inner: self.inner,
         }
     }
@@ -510,8 +956,42 @@ where
     ///   the span has.
     ///
     /// The default template is `{span_child_prefix}{spinner} {span_name}{{{span_fields}}}`.
-    pub fn with_progress_style(mut self, style: ProgressStyle) -> Self {
-        self.progress_style = style;
+    pub fn with_progress_style(self, style: ProgressStyle) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.style_settings.progress_style = style;
+        }
+
+        self
+    }
+
+    /// Uses `style` for the progress bars of spans matched by `matcher`, instead of the style set
+    /// through [`Self::with_progress_style`].
+    ///
+    /// This lets libraries say which kind of progress bar they want (e.g. through an
+    /// `indicatif.pb_style = "download"` field) without depending on indicatif, while the binary
+    /// defines what each kind looks like:
+    /// ```
+    /// use tracing_indicatif::IndicatifLayer;
+    /// use tracing_indicatif::StyleMatcher;
+    /// use tracing_indicatif::style::ProgressStyle;
+    ///
+    /// let indicatif_layer = IndicatifLayer::new()
+    ///     .with_style_rule(
+    ///         StyleMatcher::pb_style("download"),
+    ///         ProgressStyle::with_template("{span_name} {bytes}/{total_bytes}").unwrap(),
+    ///     )
+    ///     .with_style_rule(
+    ///         StyleMatcher::level(tracing::Level::DEBUG),
+    ///         ProgressStyle::with_template("{span_child_prefix}{span_name:.dim}").unwrap(),
+    ///     );
+    /// # let _: IndicatifLayer<tracing_subscriber::Registry> = indicatif_layer;
+    /// ```
+    ///
+    /// Rules are matched when a span is created, in the order they were added; the first matching
+    /// rule wins. [`pb_set_style`](span_ext::IndicatifSpanExt::pb_set_style) takes precedence over
+    /// style rules.
+    pub fn with_style_rule(mut self, matcher: StyleMatcher, style: ProgressStyle) -> Self {
+        self.style_rules.push((matcher, style));
         self
     }
 
@@ -520,8 +1000,11 @@ where
     /// For example, if the given span is two levels deep (iow has two parent spans with progress
     /// bars), and this is " ", the `{span_child_prefix}` key for this span's progress bar will be
     /// prefixed with "  ".
-    pub fn with_span_child_prefix_indent(mut self, indent: &'static str) -> Self {
-        self.span_child_prefix_indent = indent;
+    pub fn with_span_child_prefix_indent(self, indent: &'static str) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.style_settings.span_child_prefix_indent = indent;
+        }
+
         self
     }
 
@@ -529,38 +1012,357 @@ where
     ///
     /// This is ultimately concatenated with the child prefix indent to make the
     /// `span_child_prefix` progress bar key.
-    pub fn with_span_child_prefix_symbol(mut self, symbol: &'static str) -> Self {
-        self.span_child_prefix_symbol = symbol;
+    pub fn with_span_child_prefix_symbol(self, symbol: &'static str) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.style_settings.span_child_prefix_symbol = symbol;
+        }
+
         self
     }
 
     /// Set the maximum number of progress bars that will be displayed, and the possible footer
     /// "progress bar" that displays when there are more progress bars than can be displayed.
     ///
+    /// Child progress bars are only displayed while their parent's progress bar is: the children
+    /// of a pending progress bar wait along with it, and are displayed right after it.
+    ///
     /// `footer_style` dictates the appearance of the footer, and the footer will only appear if
     /// there are more progress bars than can be displayed. If it is `None`, no footer will be
     /// displayed. `footer_style` has the following keys available to it:
-    /// * `pending_progress_bars` - the number of progress bars waiting to be shown
+    /// * `pending_progress_bars` - the number of progress bars waiting to be shown, including the
+    ///   children of pending progress bars. Children waiting for a free child slot (see
+    ///   [`Self::with_max_child_progress_bars`]) are not included, they are counted by the footer
+    ///   of their parent instead
     pub fn with_max_progress_bars(
-        mut self,
+        self,
         max_progress_bars: u64,
         footer_style: Option<ProgressStyle>,
     ) -> Self {
-        if let Ok(pb_manager) = self.pb_manager.get_mut() {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
             pb_manager.set_max_progress_bars(max_progress_bars, footer_style);
         }
 
         self
     }
 
+    /// Set the maximum number of child progress bars that will be displayed per parent, and the
+    /// possible footer "progress bar" drawn below the children of a parent that has more children
+    /// than can be displayed, e.g. `↳ …and 43 more`.
+    ///
+    /// Children beyond this limit wait until one of their siblings finishes, in order of priority
+    /// (see [`Self::with_priority`]). The limit of [`Self::with_max_progress_bars`] still applies
+    /// on top of this. Pinned progress bars (see [`Self::with_pinned`]) ignore this limit.
+    ///
+    /// Children waiting for a free child slot only count towards the footer of their parent, not
+    /// towards the footer of [`Self::with_max_progress_bars`].
+    ///
+    /// `footer_style` dictates the appearance of the footer. If it is `None`, no footer will be
+    /// displayed. `footer_style` has the following keys available to it:
+    /// * `pending_progress_bars` - the number of children of the parent waiting to be shown
+    /// * `span_child_prefix` - the prefix of the children of the parent, e.g.
+    ///   `{span_child_prefix}…and {pending_progress_bars} more`
+    pub fn with_max_child_progress_bars(
+        self,
+        max_child_progress_bars: u64,
+        footer_style: Option<ProgressStyle>,
+    ) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_max_child_progress_bars(max_child_progress_bars, footer_style);
+        }
+
+        self
+    }
+
     /// Configures how often progress bars are recalcuated and redrawn to the terminal.
-    pub fn with_tick_settings(mut self, tick_settings: TickSettings) -> Self {
-        if let Ok(pb_manager) = self.pb_manager.get_mut() {
+    pub fn with_tick_settings(self, tick_settings: TickSettings) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
             pb_manager.set_tick_settings(tick_settings);
         }
 
         self
     }
+
+    /// Configures where progress bars are drawn to. Defaults to [`DrawTarget::stderr`].
+    ///
+    /// This is retained across calls to [`Self::with_tick_settings`].
+    ///
+    /// Note, this does not change where [`Self::get_stderr_writer`] and
+    /// [`Self::get_stdout_writer`] write to. If drawing to stdout, you likely want to pass
+    /// [`Self::get_stdout_writer`] to your `fmt::layer()`.
+    pub fn with_draw_target(self, draw_target: DrawTarget) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_draw_target(draw_target);
+        }
+
+        self
+    }
+
+    /// Sets the order in which progress bars with the same parent are drawn, see
+    /// [`ProgressBarOrder`]. By default, child progress bars are drawn in the order their spans
+    /// were created, and top level progress bars at the bottom as they are shown.
+    pub fn with_progress_bar_order(mut self, order: ProgressBarOrder) -> Self {
+        self.order_field = match order {
+            ProgressBarOrder::Field(name) => Some(name),
+            _ => None,
+        };
+
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_progress_bar_order(order);
+        }
+
+        self
+    }
+
+    /// Derives the length and position of the progress bars of spans from their child spans, see
+    /// [`ChildProgress`]. Use
+    /// [`IndicatifSpanExt::pb_set_child_progress`](span_ext::IndicatifSpanExt::pb_set_child_progress)
+    /// to do so for individual spans only.
+    pub fn with_child_progress(mut self, child_progress: ChildProgress) -> Self {
+        self.child_progress = Some(child_progress);
+        self
+    }
+
+    /// Sets the priority of progress bars from the metadata and depth (the number of parent spans
+    /// with progress bars) of their span. When there are more progress bars than
+    /// [`Self::with_max_progress_bars`] allows, the pending progress bar with the highest priority
+    /// is shown first once a slot frees up, pending progress bars with the same priority in the
+    /// order they were queued.
+    ///
+    /// By default, all progress bars have priority 0. The priority of individual spans can be set
+    /// with the `indicatif.pb_priority` field or
+    /// [`IndicatifSpanExt::pb_set_priority`](span_ext::IndicatifSpanExt::pb_set_priority), which
+    /// take precedence over this function.
+    ///
+    /// For example, to show top level progress bars before those of deeply nested spans, and
+    /// downloads before anything else:
+    /// ```
+    /// use tracing_indicatif::IndicatifLayer;
+    /// # use tracing_subscriber::registry::Registry;
+    ///
+    /// # let _: IndicatifLayer<Registry> =
+    /// IndicatifLayer::new().with_priority(|metadata, depth| {
+    ///     let priority = -(depth as i64);
+    ///
+    ///     if metadata.name() == "download" {
+    ///         priority + 100
+    ///     } else {
+    ///         priority
+    ///     }
+    /// });
+    /// ```
+    pub fn with_priority(
+        mut self,
+        priority: impl Fn(&tracing::Metadata<'_>, usize) -> i64 + Send + Sync + 'static,
+    ) -> Self {
+        self.priority = Some(Box::new(priority));
+        self
+    }
+
+    /// Pins the progress bars of spans matching `pinned`, e.g. the root "Overall" progress bar of
+    /// a run. Pinned progress bars never wait in the pending queue: if there is no free slot (see
+    /// [`Self::with_max_progress_bars`]), the unpinned progress bar with the lowest priority (see
+    /// [`Self::with_priority`]) is pushed back into the pending queue to make room, and shown
+    /// again before other pending progress bars with the same priority. If all shown progress bars
+    /// are pinned, pinned progress bars are shown beyond the maximum. Pinned child progress bars
+    /// still wait for their parent's progress bar to be shown.
+    ///
+    /// Pinned progress bars are drawn before their unpinned siblings, so pinned top level progress
+    /// bars stay at the top.
+    ///
+    /// Individual spans can also be pinned with the `indicatif.pb_pinned` field or
+    /// [`IndicatifSpanExt::pb_set_pinned`](span_ext::IndicatifSpanExt::pb_set_pinned), which take
+    /// precedence over `pinned`.
+    pub fn with_pinned(
+        mut self,
+        pinned: impl Fn(&tracing::Metadata<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.pinned = Some(Box::new(pinned));
+        self
+    }
+
+    /// Rotates pending progress bars in, so every progress bar is eventually seen when there are
+    /// more than [`Self::with_max_progress_bars`] allows. Once a progress bar has been shown for
+    /// `rotation_interval`, it is pushed back into the pending queue if other progress bars are
+    /// pending, and the next pending progress bar (see [`Self::with_priority`]) is shown in its
+    /// place, even if the progress bar pushed back has a higher priority. Pinned progress bars (see
+    /// [`Self::with_pinned`]) are never rotated out, nor are progress bars while progress bars of
+    /// their descendants linger (see [`Self::with_min_display_time`]).
+    ///
+    /// Progress bars keep running while pending, so they keep their elapsed time and position.
+    pub fn with_rotation(self, rotation_interval: Duration) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_rotation_interval(Some(rotation_interval));
+        }
+
+        self
+    }
+
+    /// Keeps the progress bar of a span hidden until the span has been alive for `show_delay` after
+    /// it was entered for the first time. Defaults to no delay.
+    ///
+    /// Spans that close earlier are never drawn and never count towards the maximum number of
+    /// progress bars or the footer, which avoids flashing progress bars for lots of short lived
+    /// spans. Once the delay has passed, the progress bar is shown or queued as usual.
+    ///
+    /// This can be overridden per span with
+    /// [`pb_set_show_delay`](span_ext::IndicatifSpanExt::pb_set_show_delay).
+    pub fn with_show_delay(self, show_delay: Duration) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_show_delay(show_delay);
+        }
+
+        self
+    }
+
+    /// Keeps the progress bar of a span shown for at least `min_display_time`, even if the span
+    /// closes earlier, so progress bars do not flicker in and out. Defaults to no minimum.
+    ///
+    /// Once the span closes, its progress bar keeps showing its final state (restyled with
+    /// `done_style`, if given) until it has been shown for `min_display_time`. Until then, it still
+    /// counts towards the maximum number of progress bars, so pending progress bars are only shown
+    /// once it is removed. Progress bars finishing with a message (see
+    /// [`pb_set_finish_message`](span_ext::IndicatifSpanExt::pb_set_finish_message)) are not
+    /// affected.
+    pub fn with_min_display_time(
+        self,
+        min_display_time: Duration,
+        done_style: Option<ProgressStyle>,
+    ) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_min_display_time(min_display_time, done_style);
+        }
+
+        self
+    }
+
+    /// Sets the [`Clock`] used to compute when progress bars started, their elapsed time and ETA.
+    ///
+    /// This drives the `elapsed`, `elapsed_precise`, `eta`, `eta_precise`, `duration` and
+    /// `duration_precise` progress bar template keys, as well as
+    /// [`pb_elapsed`](span_ext::IndicatifSpanExt::pb_elapsed) and
+    /// [`pb_eta`](span_ext::IndicatifSpanExt::pb_eta) and the rate limiting of plain text progress
+    /// lines (see [`TickSettings::text_line_interval`]). Note, the ETA is then a simple linear
+    /// estimate based on the elapsed time and the current position. Custom keys reading
+    /// [`ProgressState::elapsed`](indicatif::ProgressState::elapsed) are not affected.
+    ///
+    /// By default, indicatif's own time keeping is used. Setting a clock is mostly useful to make
+    /// tests of timed templates deterministic, see
+    /// `testing::ManualClock` (requires the `testing` feature).
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Sets the message (i.e. the `{msg}` key) of a progress bar to the message of the latest event
+    /// emitted inside its span, so one can see what each span is doing right now.
+    ///
+    /// Only events at `level` or more severe are considered. Events are attributed to the closest
+    /// span with a progress bar, i.e. the event's span or one of its parents. By default, the
+    /// `message` field of the event is used and events without one are ignored, see
+    /// [`Self::with_event_message_formatter`] to build the message from the event instead.
+    ///
+    /// Note, events must not be filtered out for this layer, e.g. [`filter::IndicatifFilter`] only
+    /// lets events through that report progress unless configured via
+    /// [`filter::IndicatifFilter::with_event_level`].
+    pub fn with_event_messages(mut self, level: tracing::Level) -> Self {
+        self.event_messages.level = Some(level);
+        self
+    }
+
+    /// Builds the message shown by [`Self::with_event_messages`] from the event with `formatter`,
+    /// instead of using its `message` field. Events `formatter` returns an empty string for are
+    /// ignored.
+    ///
+    /// For example, to show the level of the latest event:
+    /// ```
+    /// use tracing_indicatif::IndicatifLayer;
+    /// # use tracing_subscriber::registry::Registry;
+    ///
+    /// # let _: IndicatifLayer<Registry> =
+    /// IndicatifLayer::new()
+    ///     .with_event_messages(tracing::Level::DEBUG)
+    ///     .with_event_message_formatter(|event| event.metadata().level().to_string());
+    /// ```
+    pub fn with_event_message_formatter(
+        mut self,
+        formatter: impl Fn(&tracing::Event<'_>) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.event_messages.formatter = Some(Box::new(formatter));
+        self
+    }
+
+    /// Shows the last `max_lines` log lines (i.e. the `message` field of events at `level` or more
+    /// severe) emitted inside a span as dimmed lines right below its progress bar, e.g.
+    /// ```text
+    /// ⠄ compile{crate="foo"}
+    ///     Compiling foo v0.1.0
+    ///     warning: unused variable `x`
+    /// ```
+    ///
+    /// Events are attributed to the closest span with a progress bar. The lines disappear once the
+    /// span closes, unless an `ERROR` event was emitted inside it, see
+    /// [`Self::with_log_tail_flush`].
+    ///
+    /// Log tail lines count against the maximum number of progress bars (see
+    /// [`Self::with_max_progress_bars`]), progress bars taking precedence: the oldest lines are
+    /// hidden to make room for another progress bar, and lines are only shown if there is room for
+    /// them. Hidden lines are shown again once there is room. Log tails are not printed with
+    /// [`OutputMode::Text`] or [`OutputMode::Heartbeat`].
+    ///
+    /// Note, this does not keep these events out of your logs, and events must not be filtered out
+    /// for this layer, see [`filter::IndicatifFilter::with_event_level`].
+    pub fn with_log_tail(mut self, level: tracing::Level, max_lines: usize) -> Self {
+        self.log_tail = (max_lines > 0).then_some(LogTail { level, max_lines });
+        self
+    }
+
+    /// Prints the log tail (see [`Self::with_log_tail`]) of a span that emitted an `ERROR` event
+    /// above the progress bars once the span closes, so the context of the error is not lost.
+    /// Defaults to `true`.
+    ///
+    /// Disable this if these events are logged elsewhere, e.g. by a `fmt` layer, as the lines would
+    /// be printed twice otherwise.
+    pub fn with_log_tail_flush(mut self, flush: bool) -> Self {
+        self.log_tail_flush = flush;
+        self
+    }
+
+    /// Additionally writes progress events as newline delimited JSON to `writer`, e.g. for IDEs or
+    /// wrapper scripts that want structured progress instead of redrawn progress bars.
+    ///
+    /// An event is written whenever the progress bar of a span is created (i.e. the span is entered
+    /// for the first time), shown, queued as pending, has its position, length or message changed,
+    /// and is finished with a message or cleared when the span closes. For example:
+    /// ```text
+    /// {"event":"position","timestamp_ms":1700000000000,"span_id":1,"parent_span_id":null,"name":"do_work","fields":"val=3","elapsed_ms":12000,"pos":40,"len":100,"message":""}
+    /// ```
+    ///
+    /// This runs alongside the progress bars drawn to the terminal. Combine this with
+    /// [`DrawTarget::hidden`] to only emit JSON events.
+    pub fn with_json_events<W>(self, writer: W) -> Self
+    where
+        W: std::io::Write + Send + 'static,
+    {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_json_events(Some(Arc::new(JsonEvents::new(Box::new(writer)))));
+        }
+
+        self
+    }
+
+    /// Configures how progress is reported. Defaults to [`OutputMode::Auto`], which draws progress
+    /// bars if the draw target is a terminal and prints plain text progress lines otherwise.
+    ///
+    /// Plain text progress lines are written to the draw target through an [`IndicatifWriter`], so
+    /// they will not be clobbered by, nor clobber, other output going through the writers of this
+    /// layer.
+    pub fn with_output_mode(self, output_mode: OutputMode) -> Self {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_output_mode(output_mode);
+        }
+
+        self
+    }
 }
 
 impl<S, F> IndicatifLayer<S, F>
@@ -578,14 +1380,25 @@ where
         let subscriber = dispatch
             .downcast_ref::<S>()
             .expect("subscriber should downcast to expected type; this is a bug!");
-        let span = subscriber
-            .span(id)
-            .expect("Span not found in context, this is a bug");
+        // The span may have closed already if we are looking up a queued span.
+        let Some(span) = subscriber.span(id) else {
+            return;
+        };
 
         let mut ext = span.extensions_mut();
 
         if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
             f(indicatif_ctx);
+
+            report_child_progress(indicatif_ctx, false, |span_id, f| {
+                if let Some(span) = subscriber.span(span_id) {
+                    if let Some(indicatif_ctx) =
+                        span.extensions_mut().get_mut::<IndicatifSpanContext>()
+                    {
+                        f(indicatif_ctx);
+                    }
+                }
+            });
         }
     }
 
@@ -619,12 +1432,36 @@ where
         f(layer.mp.clone())
     }
 
+    fn get_handle_context(dispatch: &tracing::Dispatch, f: &mut dyn FnMut(IndicatifHandle)) {
+        let layer = dispatch
+            .downcast_ref::<IndicatifLayer<S, F>>()
+            .expect("subscriber should downcast to expected type; this is a bug!");
+
+        f(layer.get_handle())
+    }
+
+    // See `report_child_progress`.
+    fn report_to_parents(
+        indicatif_ctx: &mut IndicatifSpanContext,
+        closed: bool,
+        ctx: &layer::Context<'_, S>,
+    ) {
+        report_child_progress(indicatif_ctx, closed, |span_id, f| {
+            if let Some(span) = ctx.span(span_id) {
+                if let Some(indicatif_ctx) = span.extensions_mut().get_mut::<IndicatifSpanContext>()
+                {
+                    f(indicatif_ctx);
+                }
+            }
+        });
+    }
+
     fn handle_on_enter(
         &self,
         pb_manager: &mut ProgressBarManager,
         id: &span::Id,
         ctx: &layer::Context<'_, S>,
-    ) -> Option<ProgressBar> {
+    ) {
         let span = ctx
             .span(id)
             .expect("Span not found in context, this is a bug");
@@ -633,22 +1470,31 @@ where
         if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
             // Start the progress bar when we enter the span for the first time.
             if indicatif_ctx.progress_bar.is_none() {
-                indicatif_ctx.make_progress_bar(&self.progress_style);
+                indicatif_ctx.make_progress_bar(pb_manager);
+
+                let mut order_path = Vec::new();
 
                 if let Some(ref parent_span_with_pb) = indicatif_ctx.parent_span {
                     // Recursively start parent PBs if parent spans have not been entered yet.
-                    let parent_pb = self.handle_on_enter(pb_manager, parent_span_with_pb, ctx);
-
-                    indicatif_ctx.parent_progress_bar = parent_pb;
+                    self.handle_on_enter(pb_manager, parent_span_with_pb, ctx);
+
+                    if let Some(parent_span) = ctx.span(parent_span_with_pb) {
+                        if let Some(parent_indicatif_ctx) =
+                            parent_span.extensions().get::<IndicatifSpanContext>()
+                        {
+                            order_path.clone_from(&parent_indicatif_ctx.order_path);
+                        }
+                    }
                 }
 
-                pb_manager.show_progress_bar(indicatif_ctx, id);
-            }
+                order_path.push(pb_manager.order_entry(indicatif_ctx));
+                indicatif_ctx.order_path = order_path;
 
-            return indicatif_ctx.progress_bar.to_owned();
+                if pb_manager.start_progress_bar(indicatif_ctx, id) {
+                    pb_manager.ensure_background_ticker(Arc::downgrade(&self.pb_manager));
+                }
+            }
         }
-
-        None
     }
 }
 
@@ -658,11 +1504,27 @@ where
     F: for<'writer> FormatFields<'writer> + 'static,
 {
     fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: layer::Context<'_, S>) {
+        let created = self
+            .created_spans
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            + 1;
+
+        let order_field = self.order_field.and_then(|name| {
+            let mut order_field = OrderField::new(name);
+            attrs.record(&mut order_field);
+            order_field.value
+        });
+
         let span = ctx
             .span(id)
             .expect("Span not found in context, this is a bug");
         let mut ext = span.extensions_mut();
 
+        // The progress bar was hidden by `IndicatifFilter` based on a field value.
+        if ext.get_mut::<filter::HiddenProgressBar>().is_some() {
+            return;
+        }
+
         let mut fields = FormattedFields::<F>::new(String::new());
         let _ = self
             .span_field_formatter
@@ -683,33 +1545,169 @@ where
                 .expect("validated it exists prior")
         });
 
-        let (span_child_prefix, level) = match parent_indicatif_ctx {
-            Some(v) => {
-                let level = v.level + 1;
-
-                (
-                    format!(
-                        "{}{}",
-                        self.span_child_prefix_indent.repeat(level.into()),
-                        self.span_child_prefix_symbol
-                    ),
-                    level,
-                )
-            }
-            None => (String::new(), 0),
-        };
+        let level = parent_indicatif_ctx.map_or(0, |v| v.level + 1);
+        let reports_to_parent = parent_indicatif_ctx.is_some_and(|v| v.child_progress.is_some());
+        // Reporting to the parent below requires a write lock on its extensions.
+        drop(parent_span_ext);
 
         ext.insert(IndicatifSpanContext {
             progress_bar: None,
             pb_init_settings: ProgressBarInitSettings::default(),
-            parent_progress_bar: None,
+            custom_style: style_rules::match_style_rules(&self.style_rules, attrs),
             parent_span: parent_span_id,
             span_fields_formatted: Some(fields.fields),
             span_name: span.name().to_string(),
-            span_child_prefix,
+            span_child_prefix: String::new(),
             level,
             finish_message: None,
+            text_progress: None,
+            json_events: None,
+            span_id: id.clone(),
+            timer: self.clock.clone().map(SpanTimer::new),
+            log_tail: VecDeque::new(),
+            failed: false,
+            show_delay: None,
+            created,
+            order_field,
+            order_path: Vec::new(),
+            weight: 1,
+            child_progress: self.child_progress,
+            children: ChildrenProgress::default(),
+            reports_to_parent,
+            reported_state: None,
+            priority: self
+                .priority
+                .as_ref()
+                .map_or(0, |priority| priority(attrs.metadata(), level.into())),
+            pinned: self
+                .pinned
+                .as_ref()
+                .is_some_and(|pinned| pinned(attrs.metadata())),
+        });
+
+        let mut progress_fields = ProgressFields::default();
+        attrs.record(&mut progress_fields);
+
+        if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
+            progress_fields.apply(indicatif_ctx);
+
+            Self::report_to_parents(indicatif_ctx, false, &ctx);
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: layer::Context<'_, S>) {
+        let progress_fields = fields::has_progress_fields(event.metadata()).then(|| {
+            let mut progress_fields = ProgressFields::default();
+            event.record(&mut progress_fields);
+            progress_fields
         });
+        let message = self.event_messages.message(event);
+
+        let log_tail_line = self
+            .log_tail
+            .as_ref()
+            .and_then(|log_tail| Some((log_tail.line(event)?, log_tail.max_lines)));
+
+        if progress_fields.is_none() && message.is_none() && log_tail_line.is_none() {
+            return;
+        }
+
+        // Showing log tail lines requires the `ProgressBarManager`, which must be locked before
+        // any span extensions.
+        let mut pb_manager_lock = match log_tail_line {
+            Some(_) => match self.pb_manager.lock() {
+                Ok(pb_manager_lock) => Some(pb_manager_lock),
+                Err(_) => return,
+            },
+            None => None,
+        };
+
+        // Report to the closest span that has a progress bar.
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+
+        for span in scope {
+            let mut ext = span.extensions_mut();
+
+            if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
+                if let Some(message) = message {
+                    indicatif_ctx.set_progress_bar_message(message);
+                }
+
+                // Applied last, so an explicit `indicatif.pb_msg` takes precedence.
+                if let Some(progress_fields) = progress_fields {
+                    progress_fields.apply(indicatif_ctx);
+
+                    Self::report_to_parents(indicatif_ctx, false, &ctx);
+                }
+
+                if let Some((line, max_lines)) = log_tail_line {
+                    if let Some(ref mut pb_manager) = pb_manager_lock {
+                        indicatif_ctx.failed |= self.log_tail_flush
+                            && *event.metadata().level() == tracing::Level::ERROR;
+                        pb_manager.add_log_tail_line(indicatif_ctx, &line, max_lines);
+                    }
+                }
+
+                return;
+            }
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: layer::Context<'_, S>) {
+        let Ok(mut pb_manager_lock) = self.pb_manager.lock() else {
+            return;
+        };
+
+        let span = ctx
+            .span(id)
+            .expect("Span not found in context, this is a bug");
+        let mut ext = span.extensions_mut();
+
+        if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
+            // Progress fields are never merged, they would pile up as progress is recorded.
+            RecordedFields::without_progress_fields(values, span.metadata().fields(), |values| {
+                // Skip values the formatter does not output (e.g. hidden fields), otherwise
+                // `add_fields` would leave a dangling separator behind.
+                let mut recorded = FormattedFields::<F>::new(String::new());
+                let _ = self
+                    .span_field_formatter
+                    .format_fields(recorded.as_writer(), values);
+
+                if !recorded.fields.is_empty() {
+                    // Merge the recorded values the same way `fmt::Layer` does.
+                    let mut fields = FormattedFields::<F>::new(
+                        indicatif_ctx
+                            .span_fields_formatted
+                            .take()
+                            .unwrap_or_default(),
+                    );
+                    let _ = self.span_field_formatter.add_fields(&mut fields, values);
+                    indicatif_ctx.span_fields_formatted = Some(fields.fields);
+
+                    pb_manager_lock.span_fields_changed(indicatif_ctx);
+                }
+            });
+
+            let mut progress_fields = ProgressFields::default();
+            values.record(&mut progress_fields);
+            progress_fields.apply(indicatif_ctx);
+
+            Self::report_to_parents(indicatif_ctx, false, &ctx);
+        }
+    }
+
+    fn on_register_dispatch(&self, subscriber: &tracing::Dispatch) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.set_dispatch(subscriber.downgrade());
+        }
+    }
+
+    fn on_layer(&mut self, _: &mut S) {
+        if let Ok(mut pb_manager) = self.pb_manager.lock() {
+            pb_manager.ensure_background_ticker(Arc::downgrade(&self.pb_manager));
+        }
     }
 
     fn on_enter(&self, id: &span::Id, ctx: layer::Context<'_, S>) {
@@ -727,7 +1725,11 @@ where
 
             // Clear the progress bar only when the span has closed completely.
             if let Some(indicatif_ctx) = ext.get_mut::<IndicatifSpanContext>() {
-                pb_manager_lock.finish_progress_bar(indicatif_ctx, &ctx);
+                Self::report_to_parents(indicatif_ctx, true, &ctx);
+
+                if pb_manager_lock.finish_progress_bar(indicatif_ctx, &id, &ctx) {
+                    pb_manager_lock.ensure_background_ticker(Arc::downgrade(&self.pb_manager));
+                }
             }
         }
     }
@@ -751,6 +1753,9 @@ where
             id if id == TypeId::of::<WithMultiProgress>() => {
                 Some(&self.get_multi_progress_context as *const _ as *const ())
             }
+            id if id == TypeId::of::<WithHandle>() => {
+                Some(&self.get_handle_context as *const _ as *const ())
+            }
             _ => None,
         }
     }
@@ -786,6 +1791,37 @@ pub fn suspend_tracing_indicatif<F: FnOnce() -> R, R>(f: F) -> R {
     }
 }
 
+/// Stops drawing all progress bars managed by [`IndicatifLayer`] (if it exists) until
+/// [`enable_tracing_indicatif`] is called, e.g. while running an interactive prompt or when the
+/// user asked for quiet output.
+///
+/// Progress bars keep being tracked while disabled (positions, elapsed times, which progress bars
+/// are shown or queued), so they reappear in their current state once re-enabled. Plain text
+/// progress lines (see [`OutputMode::Text`]) are dropped while disabled.
+///
+/// Unlike [`suspend_tracing_indicatif`], this does not hold any lock while disabled, and
+/// [`IndicatifWriter`]s write straight through to stdout/stderr. See also
+/// [`IndicatifHandle::disable_rendering`].
+///
+/// Does nothing if there is no default tracing subscriber or if a `IndicatifLayer` has not been
+/// registered to that subscriber.
+pub fn disable_tracing_indicatif() {
+    if let Some(handle) = handle::get_indicatif_handle() {
+        handle.disable_rendering();
+    }
+}
+
+/// Draws the progress bars managed by [`IndicatifLayer`] (if it exists) again after
+/// [`disable_tracing_indicatif`] was called. See also [`IndicatifHandle::enable_rendering`].
+///
+/// Does nothing if there is no default tracing subscriber or if a `IndicatifLayer` has not been
+/// registered to that subscriber.
+pub fn enable_tracing_indicatif() {
+    if let Some(handle) = handle::get_indicatif_handle() {
+        handle.enable_rendering();
+    }
+}
+
 /// Helper macro that allows you to print to stdout without interfering with the progress bars
 /// created by tracing-indicatif.
 ///
@@ -0,0 +1,184 @@
+//! Ordering of progress bars, see
+//! [`IndicatifLayer::with_progress_bar_order`](crate::IndicatifLayer::with_progress_bar_order).
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::Duration;
+
+use tracing::field::Visit;
+use tracing_core::Field;
+use tracing_core::span;
+
+/// Controls the order in which progress bars with the same parent (or top level progress bars)
+/// are drawn. Child progress bars are always drawn right below their parent, after any progress
+/// bars of the parent's descendants that come before them. Pinned progress bars (see
+/// [`IndicatifLayer::with_pinned`](crate::IndicatifLayer::with_pinned)) come before all others.
+///
+/// The position of a progress bar is decided when it is shown, and is kept as progress bars are
+/// shown from the pending queue (see
+/// [`IndicatifLayer::with_max_progress_bars`](crate::IndicatifLayer::with_max_progress_bars)), i.e.
+/// a progress bar that has been queued takes the position it would have had if it was shown right
+/// away.
+///
+/// Unless an order is set via
+/// [`IndicatifLayer::with_progress_bar_order`](crate::IndicatifLayer::with_progress_bar_order),
+/// child progress bars are drawn in creation order, while top level progress bars are drawn
+/// below all others whenever they are shown, including when shown from the pending queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProgressBarOrder {
+    /// In the order spans were created.
+    ///
+    /// If set explicitly, this also applies to top level progress bars: a progress bar started
+    /// after spans that were created later, or shown from the pending queue, is drawn above theirs
+    /// rather than at the bottom.
+    #[default]
+    Creation,
+    /// In the order progress bars were started, i.e. spans were entered for the first time.
+    StartTime,
+    /// By span name, then in the order spans were created.
+    Name,
+    /// By the value of the given span field, then in the order spans were created. Integers are
+    /// compared numerically and come before other values, spans without the field come last.
+    Field(&'static str),
+    /// Progress bars that will finish soonest first, according to their ETA. Progress bars without
+    /// a length or progress yet come last, in the order spans were created.
+    ///
+    /// Unlike the other orders, this changes over time, so progress bars are re-sorted about once
+    /// a second.
+    Eta,
+}
+
+/// The position of a span among the spans with the same parent.
+#[derive(Clone, Debug)]
+pub(crate) struct OrderEntry {
+    pub(crate) span_id: span::Id,
+    pub(crate) created: u64,
+    pub(crate) pinned: bool,
+    pub(crate) key: OrderKey,
+}
+
+/// What spans with the same parent are sorted by before their creation order, see
+/// [`ProgressBarOrder`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum OrderKey {
+    Creation,
+    // The order top level progress bars were shown in, if no order is set.
+    Shown(u64),
+    Started(u64),
+    Name(String),
+    // `None` sorts last.
+    Field(bool, Option<FieldValue>),
+    // Compared by the current ETA of the progress bars.
+    Eta,
+}
+
+/// A position among the drawn progress bars: the progress bar of a span, or the footer drawn below
+/// the progress bars of the descendants of a span (see
+/// [`IndicatifLayer::with_max_child_progress_bars`](crate::IndicatifLayer::with_max_child_progress_bars)).
+#[derive(Clone, Copy)]
+pub(crate) struct Position<'a> {
+    // The [`OrderEntry`]s from the top level span down to the span itself.
+    path: &'a [OrderEntry],
+    footer: bool,
+}
+
+impl<'a> Position<'a> {
+    pub(crate) fn bar(path: &'a [OrderEntry]) -> Self {
+        Self {
+            path,
+            footer: false,
+        }
+    }
+
+    pub(crate) fn footer(parent_path: &'a [OrderEntry]) -> Self {
+        Self {
+            path: parent_path,
+            footer: true,
+        }
+    }
+}
+
+/// Compares two positions such that sorting them yields a depth first traversal of the span tree,
+/// with footers right after the descendants of their span.
+///
+/// `eta` returns the current ETA of the progress bar of a span, if known.
+pub(crate) fn cmp_positions(
+    a: Position<'_>,
+    b: Position<'_>,
+    eta: impl Fn(&span::Id) -> Option<Duration>,
+) -> Ordering {
+    for (a, b) in a.path.iter().zip(b.path) {
+        // Span ids can be reused once a span closed, while its progress bar is still shown.
+        if a.span_id == b.span_id && a.created == b.created {
+            continue;
+        }
+
+        let ordering = b
+            .pinned
+            .cmp(&a.pinned)
+            .then_with(|| match (&a.key, &b.key) {
+                (OrderKey::Eta, OrderKey::Eta) => {
+                    let a_eta = eta(&a.span_id).unwrap_or(Duration::MAX);
+                    let b_eta = eta(&b.span_id).unwrap_or(Duration::MAX);
+
+                    a_eta.cmp(&b_eta)
+                }
+                (a_key, b_key) => a_key.cmp(b_key),
+            });
+
+        return ordering.then(a.created.cmp(&b.created));
+    }
+
+    // A parent comes before its children, and its footer after them.
+    match a.path.len().cmp(&b.path.len()) {
+        Ordering::Less if a.footer => Ordering::Greater,
+        Ordering::Greater if b.footer => Ordering::Less,
+        Ordering::Equal => a.footer.cmp(&b.footer),
+        ordering => ordering,
+    }
+}
+
+/// The value of a span field used to sort spans, see [`ProgressBarOrder::Field`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum FieldValue {
+    Int(i128),
+    Text(String),
+}
+
+/// Extracts the value of the given field of a span.
+pub(crate) struct OrderField {
+    name: &'static str,
+    pub(crate) value: Option<FieldValue>,
+}
+
+impl OrderField {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self { name, value: None }
+    }
+}
+
+impl Visit for OrderField {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == self.name {
+            self.value = Some(FieldValue::Int(value.into()));
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == self.name {
+            self.value = Some(FieldValue::Int(value.into()));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.name {
+            self.value = Some(FieldValue::Text(value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == self.name {
+            self.value = Some(FieldValue::Text(format!("{value:?}")));
+        }
+    }
+}
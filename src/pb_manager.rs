@@ -1,23 +1,52 @@
-use std::collections::VecDeque;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
+use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 
 use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressDrawTarget;
 use indicatif::ProgressState;
+use indicatif::TermLike;
 use indicatif::style::ProgressStyle;
 use tracing_core::Subscriber;
+use tracing_core::dispatcher::WeakDispatch;
 use tracing_core::span;
 use tracing_subscriber::layer;
 use tracing_subscriber::registry::LookupSpan;
 
+use crate::IndicatifProgressKey;
 use crate::IndicatifSpanContext;
+use crate::IndicatifWriter;
+use crate::WithContext;
+use crate::clock::SpanTimer;
+use crate::json::JsonEvent;
+use crate::json::JsonEvents;
+use crate::order;
+use crate::order::OrderEntry;
+use crate::order::OrderKey;
+use crate::order::Position;
+use crate::order::ProgressBarOrder;
+use crate::text::TextOutput;
+use crate::text::format_heartbeat;
+use crate::writer;
 
 #[derive(Clone)]
 struct RequireDefault;
 
+// How often progress bars are re-sorted, see `ProgressBarOrder::Eta`.
+const ETA_SORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Controls how often progress bars are recalculated and redrawn to the terminal.
 ///
 /// This struct must be constructed as
@@ -58,6 +87,15 @@ pub struct TickSettings {
     /// spinner in your footer, you should set this to `None` as we manually redraw the footer
     /// whenever something changes.
     pub footer_tick_interval: Option<Duration>,
+    /// The minimum interval between two progress lines for the same span when progress is
+    /// reported as plain text (see [`OutputMode::Text`]). Defaults to `Duration::from_secs(1)`.
+    ///
+    /// Lines for a span starting or finishing are never delayed. The interval is measured with the
+    /// clock of the layer, see [`IndicatifLayer::with_clock`](crate::IndicatifLayer::with_clock).
+    pub text_line_interval: Duration,
+    /// How often a snapshot of all shown progress bars is printed when progress is reported as
+    /// heartbeats (see [`OutputMode::Heartbeat`]). Defaults to `Duration::from_secs(10)`.
+    pub heartbeat_interval: Duration,
     // Exists solely to require `..Default::default()` at the end of constructing this struct.
     #[doc(hidden)]
     #[allow(private_interfaces)]
@@ -70,26 +108,428 @@ impl Default for TickSettings {
             term_draw_hz: 20,
             default_tick_interval: Some(Duration::from_millis(100)),
             footer_tick_interval: None,
+            text_line_interval: Duration::from_secs(1),
+            heartbeat_interval: Duration::from_secs(10),
             require_default: RequireDefault,
         }
     }
 }
 
+/// Where progress bars are drawn to.
+///
+/// Unlike [`ProgressDrawTarget`], this can be reused to rebuild the underlying draw target, e.g.
+/// when [`TickSettings::term_draw_hz`] changes.
+#[derive(Clone)]
+pub struct DrawTarget {
+    kind: DrawTargetKind,
+}
+
+#[derive(Clone)]
+enum DrawTargetKind {
+    Stdout,
+    Stderr,
+    TermLike(Arc<dyn TermLike>),
+    Hidden,
+}
+
+impl DrawTarget {
+    /// Draw to stdout. Progress bars are hidden if stdout is not a terminal.
+    pub fn stdout() -> Self {
+        Self {
+            kind: DrawTargetKind::Stdout,
+        }
+    }
+
+    /// Draw to stderr. Progress bars are hidden if stderr is not a terminal.
+    ///
+    /// This is the default draw target.
+    pub fn stderr() -> Self {
+        Self {
+            kind: DrawTargetKind::Stderr,
+        }
+    }
+
+    /// Draw to an object that implements indicatif's [`TermLike`] trait, e.g.
+    /// [`indicatif::InMemoryTerm`].
+    pub fn term_like(term_like: Box<dyn TermLike>) -> Self {
+        Self {
+            kind: DrawTargetKind::TermLike(Arc::from(term_like)),
+        }
+    }
+
+    /// Do not draw progress bars at all.
+    pub fn hidden() -> Self {
+        Self {
+            kind: DrawTargetKind::Hidden,
+        }
+    }
+
+    fn to_progress_draw_target(&self, term_draw_hz: u8) -> ProgressDrawTarget {
+        match self.kind {
+            DrawTargetKind::Stdout => ProgressDrawTarget::stdout_with_hz(term_draw_hz),
+            DrawTargetKind::Stderr => ProgressDrawTarget::stderr_with_hz(term_draw_hz),
+            DrawTargetKind::TermLike(ref term_like) => ProgressDrawTarget::term_like_with_hz(
+                Box::new(SharedTermLike(term_like.clone())),
+                term_draw_hz,
+            ),
+            DrawTargetKind::Hidden => ProgressDrawTarget::hidden(),
+        }
+    }
+
+    // Whether nobody is watching this draw target, i.e. it is stdout or stderr but is not a
+    // terminal.
+    fn is_unattended(&self) -> bool {
+        match self.kind {
+            DrawTargetKind::Stdout => !io::stdout().is_terminal(),
+            DrawTargetKind::Stderr => !io::stderr().is_terminal(),
+            DrawTargetKind::TermLike(_) | DrawTargetKind::Hidden => false,
+        }
+    }
+
+    pub(crate) fn write_line(&self, mp: &MultiProgress, line: &str) {
+        match self.kind {
+            DrawTargetKind::Stdout => {
+                let _ = writeln!(IndicatifWriter::<writer::Stdout>::new(mp.clone()), "{line}");
+            }
+            DrawTargetKind::Stderr => {
+                let _ = writeln!(IndicatifWriter::<writer::Stderr>::new(mp.clone()), "{line}");
+            }
+            DrawTargetKind::TermLike(ref term_like) => {
+                let _ = mp.suspend(|| term_like.write_line(line));
+            }
+            DrawTargetKind::Hidden => {}
+        }
+    }
+}
+
+impl Default for DrawTarget {
+    fn default() -> Self {
+        Self::stderr()
+    }
+}
+
+/// Controls how progress is reported by [`IndicatifLayer`](crate::IndicatifLayer).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputMode {
+    /// Draw progress bars in place if the [`DrawTarget`] is a terminal, otherwise fall back to
+    /// [`OutputMode::Text`].
+    #[default]
+    Auto,
+    /// Always draw progress bars in place.
+    ProgressBars,
+    /// Print plain text lines whenever a span starts, finishes or makes progress, e.g.
+    /// ```text
+    /// [do_work{val=3}] started
+    /// [do_work{val=3}] 40/100 (12s)
+    /// [do_work{val=3}] finished (30s)
+    /// ```
+    ///
+    /// Progress lines are rate limited per span, see [`TickSettings::text_line_interval`].
+    Text,
+    /// Periodically print a snapshot of all progress bars that would be shown, e.g.
+    /// ```text
+    /// [progress] 3 active, 2 pending
+    /// do_work{val=0} 40/100 (12s)
+    ///   ↳ do_sub_work{val=0} (3s)
+    /// do_work{val=1} (12s)
+    /// ```
+    ///
+    /// This is useful for long running jobs in CI, where redrawing in place is not possible but
+    /// going quiet for minutes looks like a hang. Only as many progress bars as set by
+    /// [`IndicatifLayer::with_max_progress_bars`](crate::IndicatifLayer::with_max_progress_bars)
+    /// are listed. The interval is controlled by [`TickSettings::heartbeat_interval`].
+    Heartbeat,
+}
+
+// `ProgressDrawTarget::term_like` takes ownership of a `Box<dyn TermLike>`, but we need to be
+// able to rebuild the draw target whenever the tick settings change, so we share the underlying
+// term instead.
+struct SharedTermLike(Arc<dyn TermLike>);
+
+impl fmt::Debug for SharedTermLike {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl TermLike for SharedTermLike {
+    fn width(&self) -> u16 {
+        self.0.width()
+    }
+
+    fn height(&self) -> u16 {
+        self.0.height()
+    }
+
+    fn move_cursor_up(&self, n: usize) -> io::Result<()> {
+        self.0.move_cursor_up(n)
+    }
+
+    fn move_cursor_down(&self, n: usize) -> io::Result<()> {
+        self.0.move_cursor_down(n)
+    }
+
+    fn move_cursor_right(&self, n: usize) -> io::Result<()> {
+        self.0.move_cursor_right(n)
+    }
+
+    fn move_cursor_left(&self, n: usize) -> io::Result<()> {
+        self.0.move_cursor_left(n)
+    }
+
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        self.0.write_line(s)
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.0.write_str(s)
+    }
+
+    fn clear_line(&self) -> io::Result<()> {
+        self.0.clear_line()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Settings that determine how the progress bar of a span is styled.
+#[derive(Clone)]
+pub(crate) struct StyleSettings {
+    pub(crate) progress_style: ProgressStyle,
+    pub(crate) span_child_prefix_indent: &'static str,
+    pub(crate) span_child_prefix_symbol: &'static str,
+}
+
+impl StyleSettings {
+    pub(crate) fn span_child_prefix(&self, level: u16) -> String {
+        if level == 0 {
+            return String::new();
+        }
+
+        format!(
+            "{}{}",
+            self.span_child_prefix_indent.repeat(level.into()),
+            self.span_child_prefix_symbol
+        )
+    }
+
+    // Log tail lines are indented one level deeper than the progress bar they belong to.
+    pub(crate) fn log_tail_prefix(&self, level: u16) -> String {
+        self.span_child_prefix_indent.repeat(usize::from(level) + 1)
+    }
+}
+
+impl Default for StyleSettings {
+    fn default() -> Self {
+        Self {
+            progress_style: ProgressStyle::with_template(
+                "{span_child_prefix}{spinner} {span_name}{{{span_fields}}}",
+            )
+            .expect("valid template"),
+            span_child_prefix_indent: "  ",
+            span_child_prefix_symbol: "↳ ",
+        }
+    }
+}
+
+// A progress bar that is currently shown.
+pub(crate) struct VisibleBar {
+    pub(crate) span_id: span::Id,
+    pub(crate) pb: ProgressBar,
+    pub(crate) span_child_prefix: String,
+    pub(crate) label: String,
+    pub(crate) timer: Option<SpanTimer>,
+    // One progress bar per shown log tail line, drawn right below `pb`, oldest line first.
+    pub(crate) log_tail: Vec<ProgressBar>,
+    // The lines of the log tail, including those hidden for lack of room.
+    log_tail_lines: Vec<String>,
+    level: u16,
+    shown_at: Instant,
+    order_path: Vec<OrderEntry>,
+    priority: i64,
+    pinned: bool,
+    parent_span: Option<span::Id>,
+}
+
+impl VisibleBar {
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.timer
+            .as_ref()
+            .map_or_else(|| self.pb.elapsed(), SpanTimer::elapsed)
+    }
+
+    // `None` until the progress bar has a length and made some progress.
+    fn eta(&self) -> Option<Duration> {
+        let len = self.pb.length()?;
+        let pos = self.pb.position();
+
+        if pos == 0 {
+            return None;
+        }
+
+        Some(
+            self.timer
+                .as_ref()
+                .map_or_else(|| self.pb.eta(), |timer| timer.eta(pos, Some(len))),
+        )
+    }
+}
+
+// A span waiting for a free slot. Children of a progress bar that was just shown are shown first,
+// then the span with the highest priority, spans with the same priority in the order they were
+// queued.
+#[derive(PartialEq, Eq)]
+struct PendingSpan {
+    with_parent: bool,
+    priority: i64,
+    // Negative for progress bars pushed back by a pinned progress bar, so they are shown again
+    // before the others.
+    queued: i64,
+    span_id: span::Id,
+}
+
+impl Ord for PendingSpan {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.with_parent
+            .cmp(&other.with_parent)
+            .then(self.priority.cmp(&other.priority))
+            .then_with(|| other.queued.cmp(&self.queued))
+    }
+}
+
+impl PartialOrd for PendingSpan {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The children of a span waiting for a free child slot, see
+// `IndicatifLayer::with_max_child_progress_bars`. Unlike the global queue, this never contains
+// spans that have closed.
+struct ChildQueue {
+    pending_spans: BinaryHeap<PendingSpan>,
+    // Shown in the footer, i.e. `pending_spans.len()`.
+    pending_progress_bars: Arc<AtomicUsize>,
+    // The position of the parent, the footer is drawn right after the parent's descendants.
+    order_path: Vec<OrderEntry>,
+    // The level of the children, the footer is indented like them.
+    level: u16,
+    // Only `Some` while the footer is shown.
+    footer_pb: Option<ProgressBar>,
+}
+
+impl ChildQueue {
+    fn footer_style(
+        &self,
+        footer_style: &ProgressStyle,
+        style_settings: &StyleSettings,
+    ) -> ProgressStyle {
+        let pending_progress_bars = self.pending_progress_bars.clone();
+
+        footer_style
+            .clone()
+            .with_key(
+                "pending_progress_bars",
+                move |_: &ProgressState, writer: &mut dyn std::fmt::Write| {
+                    let _ = write!(
+                        writer,
+                        "{}",
+                        pending_progress_bars.load(std::sync::atomic::Ordering::Acquire)
+                    );
+                },
+            )
+            .with_key(
+                "span_child_prefix",
+                IndicatifProgressKey {
+                    message: style_settings.span_child_prefix(self.level),
+                },
+            )
+    }
+}
+
+// The progress bar of a closed span that is kept shown, see
+// `IndicatifLayer::with_min_display_time`.
+struct LingeringBar {
+    pb: ProgressBar,
+    remove_at: Instant,
+    // Kept so progress bars shown meanwhile are drawn in order around it.
+    order_path: Vec<OrderEntry>,
+}
+
 pub(crate) struct ProgressBarManager {
     pub(crate) mp: MultiProgress,
     active_progress_bars: u64,
+    // Log tail lines count against `max_progress_bars` as well, see
+    // `IndicatifLayer::with_log_tail`.
+    shown_log_tail_lines: u64,
     max_progress_bars: u64,
     // This is used in the footer progress bar and tracks the actual number of pending progress
     // bars.
     pending_progress_bars: Arc<AtomicUsize>,
     // The `.len()` of this may differ from `pending_progress_bars`. If a span closes before its
     // progress bar is ever un-hidden, we decrement `pending_progress_bars` but won't clean the
-    // span entry up from this `VecDeque` for performance reasons. Instead, whenever we do un-hide
+    // span entry up from this `BinaryHeap` for performance reasons. Instead, whenever we do un-hide
     // a progress bar, we'll "garbage collect" closed spans from this then.
-    pending_spans: VecDeque<span::Id>,
+    pending_spans: BinaryHeap<PendingSpan>,
+    // Used to number pending spans in the order they were queued.
+    queued_spans: i64,
+    // Progress bars that are currently shown, in the order they are drawn. We cannot rely on
+    // `ProgressBar::is_hidden` to tell whether a progress bar is shown, as every progress bar
+    // reports itself as hidden if the draw target is hidden (e.g. stderr is not a terminal).
+    visible_bars: Vec<VisibleBar>,
     // If this is `None`, a footer will never be shown.
     footer_pb: Option<ProgressBar>,
     tick_settings: TickSettings,
+    draw_target: DrawTarget,
+    output_mode: OutputMode,
+    // Only `Some` if progress is currently reported as plain text.
+    text_output: Option<Arc<TextOutput>>,
+    // Only `Some` if progress is currently reported as heartbeats.
+    heartbeat_output: Option<TextOutput>,
+    last_heartbeat: Instant,
+    json_events: Option<Arc<JsonEvents>>,
+    pub(crate) style_settings: StyleSettings,
+    // Shared with the writers of our layer, which write straight through while this is `false`.
+    pub(crate) rendering_enabled: Arc<AtomicBool>,
+    // The dispatch our layer is registered with, used to look up span contexts outside of layer
+    // callbacks (e.g. when settings are changed through an `IndicatifHandle`).
+    dispatch: Option<WeakDispatch>,
+    // How long spans must be alive before their progress bar is shown, unless set per span.
+    show_delay: Duration,
+    // Spans whose progress bar is not yet eligible to be shown, with the instant it becomes
+    // eligible. These are neither shown nor pending.
+    delayed_spans: Vec<(span::Id, Instant)>,
+    // Only `Some` while the background ticker thread is running.
+    background_ticker: Option<thread::Thread>,
+    // How long progress bars stay shown at least, even if their span closes earlier.
+    min_display_time: Duration,
+    // If `Some`, progress bars are restyled with this style while they linger.
+    done_style: Option<ProgressStyle>,
+    // Progress bars of closed spans that are kept shown until their minimum display time has
+    // passed. These still count towards `active_progress_bars`.
+    lingering_bars: Vec<LingeringBar>,
+    // If `None`, child progress bars are drawn in the order their spans were created and top level
+    // progress bars in the order they are shown, see `IndicatifLayer::with_progress_bar_order`.
+    order: Option<ProgressBarOrder>,
+    // Used to number progress bars in the order they were started.
+    started_progress_bars: u64,
+    // Used to number top level progress bars in the order they are shown, if no order is set.
+    shown_progress_bars: u64,
+    // When progress bars were last re-sorted, see `ProgressBarOrder::Eta`.
+    last_sort: Instant,
+    // If `Some`, shown progress bars make room for pending ones after this long, see
+    // `IndicatifLayer::with_rotation`.
+    rotation_interval: Option<Duration>,
+    // If `Some`, children beyond this many per parent wait in `child_queues`.
+    max_child_progress_bars: Option<u64>,
+    child_footer_style: Option<ProgressStyle>,
+    child_queues: HashMap<span::Id, ChildQueue>,
+    // Spans waiting for the progress bar of their parent to be shown, by parent. These count
+    // towards `pending_progress_bars`, so the footer reflects whole subtrees.
+    waiting_spans: HashMap<span::Id, Vec<PendingSpan>>,
 }
 
 impl ProgressBarManager {
@@ -98,23 +538,49 @@ impl ProgressBarManager {
         footer_progress_style: Option<ProgressStyle>,
         tick_settings: TickSettings,
     ) -> Self {
-        let mut s = Self {
-            mp: {
-                let mp = MultiProgress::new();
-                mp.set_draw_target(ProgressDrawTarget::stderr_with_hz(
-                    tick_settings.term_draw_hz,
-                ));
+        let draw_target = DrawTarget::default();
 
-                mp
-            },
+        let mut s = Self {
+            mp: MultiProgress::with_draw_target(
+                draw_target.to_progress_draw_target(tick_settings.term_draw_hz),
+            ),
             active_progress_bars: 0,
+            shown_log_tail_lines: 0,
             max_progress_bars: 0,
             pending_progress_bars: Arc::new(AtomicUsize::new(0)),
-            pending_spans: VecDeque::new(),
+            pending_spans: BinaryHeap::new(),
+            queued_spans: 0,
+            visible_bars: Vec::new(),
             footer_pb: None,
             tick_settings,
+            draw_target,
+            output_mode: OutputMode::default(),
+            text_output: None,
+            heartbeat_output: None,
+            last_heartbeat: Instant::now(),
+            json_events: None,
+            style_settings: StyleSettings::default(),
+            rendering_enabled: Arc::new(AtomicBool::new(true)),
+            dispatch: None,
+            show_delay: Duration::ZERO,
+            delayed_spans: Vec::new(),
+            background_ticker: None,
+            min_display_time: Duration::ZERO,
+            done_style: None,
+            lingering_bars: Vec::new(),
+            order: None,
+            started_progress_bars: 0,
+            shown_progress_bars: 0,
+            last_sort: Instant::now(),
+            rotation_interval: None,
+            max_child_progress_bars: None,
+            child_footer_style: None,
+            child_queues: HashMap::new(),
+            waiting_spans: HashMap::new(),
         };
 
+        s.apply_output_settings();
+
         s.set_max_progress_bars(max_progress_bars, footer_progress_style);
 
         s
@@ -127,6 +593,10 @@ impl ProgressBarManager {
     ) {
         self.max_progress_bars = max_progress_bars;
 
+        if self.footer_is_shown() {
+            self.hide_footer();
+        }
+
         let pending_progress_bars = self.pending_progress_bars.clone();
         self.footer_pb = footer_style.map(move |style| {
             ProgressBar::hidden().with_style(style.with_key(
@@ -140,172 +610,1498 @@ impl ProgressBarManager {
                 },
             ))
         });
+
+        if self.footer_is_shown() {
+            self.show_footer();
+        }
+    }
+
+    pub(crate) fn set_max_child_progress_bars(
+        &mut self,
+        max_child_progress_bars: u64,
+        footer_style: Option<ProgressStyle>,
+    ) {
+        self.max_child_progress_bars = Some(max_child_progress_bars);
+        self.child_footer_style = footer_style;
     }
 
     pub(crate) fn set_tick_settings(&mut self, tick_settings: TickSettings) {
-        self.mp.set_draw_target(ProgressDrawTarget::stderr_with_hz(
-            tick_settings.term_draw_hz,
-        ));
         self.tick_settings = tick_settings;
+        self.apply_output_settings();
+
+        for bar in &self.visible_bars {
+            match self.tick_settings.default_tick_interval {
+                Some(tick_interval) => bar.pb.enable_steady_tick(tick_interval),
+                None => bar.pb.disable_steady_tick(),
+            }
+        }
+
+        if let Some(ref footer_pb) = self.footer_pb {
+            if self.footer_is_shown() {
+                match self.tick_settings.footer_tick_interval {
+                    Some(tick_interval) => footer_pb.enable_steady_tick(tick_interval),
+                    None => footer_pb.disable_steady_tick(),
+                }
+            }
+        }
     }
 
-    fn decrement_pending_pb(&mut self) {
-        let prev_val = self
-            .pending_progress_bars
-            .fetch_sub(1, std::sync::atomic::Ordering::AcqRel);
+    pub(crate) fn set_rendering_enabled(&mut self, enabled: bool) {
+        if enabled {
+            // Writers must go back to suspending progress bars before we draw them again.
+            self.rendering_enabled
+                .store(true, std::sync::atomic::Ordering::Release);
+            self.apply_output_settings();
 
-        if let Some(footer_pb) = self.footer_pb.as_ref() {
-            // If this span was the last one pending, clear the footer (if it was active).
-            if prev_val == 1 {
-                debug_assert!(
-                    !footer_pb.is_hidden(),
-                    "footer progress bar was hidden despite there being pending progress bars"
-                );
+            // Progress bars without a steady tick would otherwise only be redrawn on their next
+            // update.
+            for bar in &self.visible_bars {
+                bar.pb.tick();
 
-                if self.tick_settings.footer_tick_interval.is_some() {
-                    footer_pb.disable_steady_tick();
+                for line_pb in &bar.log_tail {
+                    line_pb.tick();
                 }
+            }
 
-                // Appears to have broken with
-                // https://github.com/console-rs/indicatif/pull/648
-                // self.mp.set_move_cursor(false);
-                footer_pb.finish_and_clear();
-                self.mp.remove(footer_pb);
-            } else {
-                footer_pb.tick();
+            if let Some(ref footer_pb) = self.footer_pb {
+                if self.footer_is_shown() {
+                    footer_pb.tick();
+                }
             }
+        } else {
+            // Progress bars must be cleared before writers stop suspending them. Swapping the draw
+            // target leaves whatever was drawn last on the terminal, hence the explicit clear.
+            let _ = self.mp.clear();
+            self.mp.set_draw_target(ProgressDrawTarget::hidden());
+            self.rendering_enabled
+                .store(false, std::sync::atomic::Ordering::Release);
         }
     }
 
-    fn add_pending_pb(&mut self, span_id: &span::Id) {
-        let prev_val = self
-            .pending_progress_bars
-            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
-        self.pending_spans.push_back(span_id.clone());
+    pub(crate) fn set_dispatch(&mut self, dispatch: WeakDispatch) {
+        self.dispatch = Some(dispatch);
+    }
 
-        // Show the footer progress bar.
-        if let Some(footer_pb) = self.footer_pb.as_ref() {
-            if prev_val == 0 {
-                debug_assert!(
-                    footer_pb.is_hidden(),
-                    "footer progress bar was not hidden despite there being no pending progress bars"
-                );
+    // Calls `f` with the context of the given span, if the span still exists. Requires our layer
+    // to have been registered with a dispatch.
+    fn with_span_context(&self, span_id: &span::Id, f: impl FnMut(&mut IndicatifSpanContext)) {
+        let Some(dispatch) = self.dispatch.as_ref().and_then(WeakDispatch::upgrade) else {
+            return;
+        };
+
+        if let Some(ctx) = dispatch.downcast_ref::<WithContext>() {
+            ctx.with_context(&dispatch, span_id, f);
+        }
+    }
+
+    /// Updates the style settings and restyles all existing progress bars that use them.
+    pub(crate) fn update_style_settings(&mut self, f: impl FnOnce(&mut StyleSettings)) {
+        f(&mut self.style_settings);
+
+        let span_ids = self
+            .visible_bars
+            .iter()
+            .map(|bar| bar.span_id.clone())
+            .chain(
+                self.pending_spans
+                    .iter()
+                    .chain(
+                        self.child_queues
+                            .values()
+                            .flat_map(|queue| queue.pending_spans.iter()),
+                    )
+                    .chain(self.waiting_spans.values().flatten())
+                    .map(|pending| pending.span_id.clone()),
+            )
+            .chain(
+                self.delayed_spans
+                    .iter()
+                    .map(|(span_id, _)| span_id.clone()),
+            )
+            .collect::<Vec<_>>();
+
+        for span_id in span_ids {
+            let mut prefixes = None;
 
-                footer_pb.reset();
+            self.with_span_context(&span_id, |pb_span_ctx| {
+                pb_span_ctx.apply_style_settings(&self.style_settings);
+                prefixes = Some((
+                    pb_span_ctx.span_child_prefix.clone(),
+                    self.style_settings.log_tail_prefix(pb_span_ctx.level),
+                ));
+            });
+
+            if let Some((span_child_prefix, log_tail_prefix)) = prefixes {
+                if let Some(bar) = self
+                    .visible_bars
+                    .iter_mut()
+                    .find(|bar| bar.span_id == span_id)
+                {
+                    bar.span_child_prefix = span_child_prefix;
 
-                if let Some(tick_interval) = self.tick_settings.footer_tick_interval {
-                    footer_pb.enable_steady_tick(tick_interval);
+                    for line_pb in &bar.log_tail {
+                        line_pb.set_prefix(log_tail_prefix.clone());
+                    }
                 }
+            }
+        }
 
-                self.mp.add(footer_pb.clone());
-                // Appears to have broken with
-                // https://github.com/console-rs/indicatif/pull/648
-                // self.mp.set_move_cursor(true);
+        if let Some(ref footer_style) = self.child_footer_style {
+            for queue in self.child_queues.values() {
+                if let Some(ref footer_pb) = queue.footer_pb {
+                    footer_pb.set_style(queue.footer_style(footer_style, &self.style_settings));
+                    footer_pb.tick();
+                }
             }
+        }
+    }
 
-            footer_pb.tick();
+    /// Refreshes everything derived from the formatted fields of a span after they changed.
+    pub(crate) fn span_fields_changed(&mut self, pb_span_ctx: &mut IndicatifSpanContext) {
+        pb_span_ctx.apply_style_settings(&self.style_settings);
+
+        if let Some(bar) = self
+            .visible_bars
+            .iter_mut()
+            .find(|bar| bar.span_id == pb_span_ctx.span_id)
+        {
+            bar.label = pb_span_ctx.text_label();
         }
     }
 
-    pub(crate) fn show_progress_bar(
+    /// Adds a line to the log tail of a span and shows it below the span's progress bar, as far as
+    /// there are free slots.
+    pub(crate) fn add_log_tail_line(
         &mut self,
         pb_span_ctx: &mut IndicatifSpanContext,
-        span_id: &span::Id,
+        line: &str,
+        max_lines: usize,
     ) {
-        if self.active_progress_bars < self.max_progress_bars {
-            let Some(pb) = pb_span_ctx.progress_bar.take() else {
-                return;
-            };
+        for line in line.lines() {
+            if pb_span_ctx.log_tail.len() >= max_lines {
+                pb_span_ctx.log_tail.pop_front();
+            }
 
-            let pb = match pb_span_ctx.parent_progress_bar {
-                // TODO(emersonford): fix span ordering in progress bar, because we use
-                // `insert_after`, we end up showing the child progress bars in reverse order.
-                Some(ref parent_pb) => self.mp.insert_after(parent_pb, pb),
-                None => {
-                    if self
-                        .footer_pb
-                        .as_ref()
-                        .map(|footer_pb| !footer_pb.is_hidden())
-                        .unwrap_or(false)
-                    {
-                        self.mp.insert_from_back(1, pb)
-                    } else {
-                        self.mp.add(pb)
-                    }
-                }
-            };
+            pb_span_ctx.log_tail.push_back(line.to_string());
+        }
 
-            self.active_progress_bars += 1;
+        self.show_log_tail(pb_span_ctx);
+    }
 
-            if let Some(tick_interval) = self.tick_settings.default_tick_interval {
-                pb.enable_steady_tick(tick_interval);
+    // Shows as many lines of the log tail of the given span as there are free slots and updates the
+    // lines that are already shown.
+    fn show_log_tail(&mut self, pb_span_ctx: &IndicatifSpanContext) {
+        // Log tails are only ever drawn alongside progress bars.
+        if !self.draws_progress_bars() {
+            return;
+        }
+
+        let Some(bar_idx) = self
+            .visible_bars
+            .iter()
+            .position(|bar| bar.span_id == pb_span_ctx.span_id)
+        else {
+            return;
+        };
+
+        self.visible_bars[bar_idx].log_tail_lines = pb_span_ctx.log_tail.iter().cloned().collect();
+        self.show_log_tail_lines(bar_idx);
+    }
+
+    // Shows log tail lines that were hidden for lack of room, now that there may be room again.
+    fn show_hidden_log_tail_lines(&mut self) {
+        if !self.draws_progress_bars() {
+            return;
+        }
+
+        for bar_idx in 0..self.visible_bars.len() {
+            if self.active_progress_bars + self.shown_log_tail_lines >= self.max_progress_bars {
+                return;
             }
 
-            pb.tick();
+            self.show_log_tail_lines(bar_idx);
+        }
+    }
 
-            pb_span_ctx.progress_bar = Some(pb);
-        } else {
-            self.add_pending_pb(span_id);
+    fn show_log_tail_lines(&mut self, bar_idx: usize) {
+        while self.visible_bars[bar_idx].log_tail.len()
+            < self.visible_bars[bar_idx].log_tail_lines.len()
+            && self.active_progress_bars + self.shown_log_tail_lines < self.max_progress_bars
+        {
+            let bar = &mut self.visible_bars[bar_idx];
+            let line_pb = ProgressBar::hidden()
+                .with_style(
+                    ProgressStyle::with_template("{prefix}{wide_msg:.dim}")
+                        .expect("valid template"),
+                )
+                .with_prefix(self.style_settings.log_tail_prefix(bar.level));
+            let line_pb = self
+                .mp
+                .insert_after(bar.log_tail.last().unwrap_or(&bar.pb), line_pb);
+
+            bar.log_tail.push(line_pb);
+            self.shown_log_tail_lines += 1;
+        }
+
+        // Show the latest lines if not all of them fit.
+        let bar = &self.visible_bars[bar_idx];
+        let skip = bar.log_tail_lines.len() - bar.log_tail.len();
+
+        for (line_pb, line) in bar
+            .log_tail
+            .iter()
+            .zip(bar.log_tail_lines.iter().skip(skip))
+        {
+            line_pb.set_message(line.clone());
         }
     }
 
-    pub(crate) fn finish_progress_bar<S>(
-        &mut self,
-        pb_span_ctx: &mut IndicatifSpanContext,
-        ctx: &layer::Context<'_, S>,
-    ) where
-        S: Subscriber + for<'a> LookupSpan<'a>,
-    {
-        let Some(pb) = pb_span_ctx.progress_bar.take() else {
-            // Span was never entered.
+    // Hides the oldest shown log tail line of the progress bar with the most shown log tail lines,
+    // to make room for another progress bar. Returns whether a line was hidden.
+    fn hide_log_tail_line(&mut self) -> bool {
+        let Some(bar) = self
+            .visible_bars
+            .iter_mut()
+            .filter(|bar| !bar.log_tail.is_empty())
+            .max_by_key(|bar| bar.log_tail.len())
+        else {
+            return false;
+        };
+
+        // The remaining progress bars already show the latest lines.
+        let line_pb = bar.log_tail.remove(0);
+        line_pb.finish_and_clear();
+        self.mp.remove(&line_pb);
+        self.shown_log_tail_lines -= 1;
+
+        true
+    }
+
+    // Pushes the unpinned progress bar with the lowest priority (the most recently shown one among
+    // those) back into the pending queue, to make room for a pinned progress bar. Ancestors of the
+    // pinned progress bar (i.e. on its `order_path`) stay shown. Only progress bars without shown
+    // or lingering children are pushed back, as their children would be hidden along with them,
+    // freeing more slots than needed, or left behind below an unrelated progress bar.
+    fn evict_progress_bar(&mut self, order_path: &[OrderEntry]) {
+        let Some(visible_bar_idx) = self
+            .visible_bars
+            .iter()
+            .enumerate()
+            .filter(|(_, bar)| {
+                !bar.pinned
+                    && !order_path.iter().any(|entry| entry.span_id == bar.span_id)
+                    && !self
+                        .visible_bars
+                        .iter()
+                        .any(|child| child.parent_span.as_ref() == Some(&bar.span_id))
+                    && !self.has_lingering_descendants(&bar.span_id)
+            })
+            .min_by_key(|(_, bar)| (bar.priority, std::cmp::Reverse(bar.shown_at)))
+            .map(|(idx, _)| idx)
+        else {
             return;
         };
 
-        // The span closed before we had a chance to show its progress bar.
-        if pb.is_hidden() {
-            self.decrement_pending_pb();
+        self.hide_progress_bar(visible_bar_idx, true);
+    }
+
+    // Pushes the unpinned progress bars that have been shown for the rotation interval back into
+    // the pending queue, as long as other progress bars are pending, and shows pending progress
+    // bars in their place.
+    fn rotate_progress_bars(&mut self, now: Instant) {
+        let Some(rotation_interval) = self.rotation_interval else {
             return;
-        }
+        };
 
-        // This span had an active/shown progress bar.
-        if let Some(finish_message) = pb_span_ctx.finish_message.take() {
-            pb.finish_with_message(finish_message);
-        } else {
-            pb.finish_and_clear();
-            self.mp.remove(&pb);
+        if !self.rotates_progress_bars() {
+            return;
         }
-        self.active_progress_bars -= 1;
 
-        loop {
-            let Some(span_id) = self.pending_spans.pop_front() else {
-                break;
-            };
+        // The progress bars shown the longest make room first.
+        let mut rotated = self
+            .visible_bars
+            .iter()
+            .filter(|bar| {
+                !bar.pinned
+                    && now.duration_since(bar.shown_at) >= rotation_interval
+                    && !self.has_lingering_descendants(&bar.span_id)
+            })
+            .map(|bar| (bar.shown_at, bar.span_id.clone(), bar.parent_span.clone()))
+            .collect::<Vec<_>>();
+        rotated.sort_by_key(|(shown_at, _, _)| *shown_at);
 
-            match ctx.span(&span_id) {
-                Some(next_eligible_span) => {
-                    let mut ext = next_eligible_span.extensions_mut();
-                    let indicatif_span_ctx = ext
-                        .get_mut::<IndicatifSpanContext>()
-                        .expect("No IndicatifSpanContext found; this is a bug");
-
-                    // It possible `on_close` has been called on a span but it has not yet been
-                    // removed from `ctx.span` (e.g., tracing may still be iterating through each
-                    // layer's `on_close` method and cannot remove the span from the registry until
-                    // it has finished `on_close` for each layer). So we may successfully fetch the
-                    // span, despite having closed out its progress bar.
-                    if indicatif_span_ctx.progress_bar.is_none() {
-                        continue;
-                    }
+        // How many progress bars can still make room for pending ones, per queue.
+        let mut pending = self
+            .pending_progress_bars
+            .load(std::sync::atomic::Ordering::Acquire);
+        let mut pending_children = self
+            .child_queues
+            .iter()
+            .map(|(parent_span, queue)| (parent_span.clone(), queue.pending_spans.len()))
+            .collect::<HashMap<_, _>>();
 
-                    self.decrement_pending_pb();
-                    self.show_progress_bar(indicatif_span_ctx, &span_id);
-                    break;
-                }
-                None => {
-                    // Span was closed earlier, we "garbage collect" it from the queue here.
-                    continue;
-                }
+        let mut rotated_out = Vec::new();
+
+        for (_, span_id, parent_span) in rotated {
+            // Children make room for their waiting siblings first.
+            match parent_span.and_then(|parent_span| pending_children.get_mut(&parent_span)) {
+                Some(pending_children) if *pending_children > 0 => *pending_children -= 1,
+                _ if pending > 0 => pending -= 1,
+                _ => continue,
+            }
+
+            if let Some(visible_bar_idx) = self
+                .visible_bars
+                .iter()
+                .position(|bar| bar.span_id == span_id)
+            {
+                rotated_out.push(self.take_progress_bar(visible_bar_idx, false));
             }
         }
-    }
+
+        // The progress bars rotated out are only queued once their slots have been taken, as they
+        // may outrank the pending progress bars and would be shown again right away.
+        self.show_pending_progress_bars();
+
+        for bar in rotated_out {
+            self.queue_hidden_progress_bar(bar, false);
+        }
+
+        // In case fewer progress bars than expected could be shown, e.g. as spans closed.
+        self.show_pending_progress_bars();
+    }
+
+    // Moves a shown progress bar back into the pending queue, or behind its waiting siblings, along
+    // with its descendants. The progress bar keeps running hidden, so it keeps its elapsed time and
+    // position.
+    fn hide_progress_bar(&mut self, visible_bar_idx: usize, evicted: bool) {
+        let bar = self.take_progress_bar(visible_bar_idx, evicted);
+        self.queue_hidden_progress_bar(bar, evicted);
+    }
+
+    // Hides a shown progress bar, along with its descendants, which wait for it to be shown again.
+    // The progress bar itself must be queued with `queue_hidden_progress_bar`.
+    fn take_progress_bar(&mut self, visible_bar_idx: usize, evicted: bool) -> VisibleBar {
+        let mut bar = self.visible_bars.remove(visible_bar_idx);
+
+        // The lines are shown again along with the progress bar.
+        for line_pb in bar.log_tail.drain(..) {
+            line_pb.finish_and_clear();
+            self.mp.remove(&line_pb);
+            self.shown_log_tail_lines -= 1;
+        }
+
+        if self.tick_settings.default_tick_interval.is_some() {
+            bar.pb.disable_steady_tick();
+        }
+
+        self.mp.remove(&bar.pb);
+        self.active_progress_bars -= 1;
+
+        // Children are only shown along with their parent, so they wait for it as well.
+        while let Some(child_idx) = self
+            .visible_bars
+            .iter()
+            .position(|child| child.parent_span.as_ref() == Some(&bar.span_id))
+        {
+            self.hide_progress_bar(child_idx, evicted);
+        }
+
+        if let Some(queue) = self.child_queues.remove(&bar.span_id) {
+            if let Some(footer_pb) = queue.footer_pb {
+                footer_pb.finish_and_clear();
+                self.mp.remove(&footer_pb);
+            }
+
+            for pending in queue.pending_spans {
+                self.add_waiting_span(&bar.span_id, pending);
+            }
+        }
+
+        bar
+    }
+
+    // Queues a progress bar hidden by `take_progress_bar` to be shown again.
+    fn queue_hidden_progress_bar(&mut self, bar: VisibleBar, evicted: bool) {
+        self.with_span_context(&bar.span_id, |pb_span_ctx| {
+            pb_span_ctx.report_json_event(JsonEvent::Pending, &bar.pb);
+        });
+
+        let pending = PendingSpan {
+            with_parent: false,
+            priority: bar.priority,
+            queued: self.next_queued(evicted),
+            span_id: bar.span_id,
+        };
+
+        match bar.parent_span {
+            Some(parent_span) if !self.is_shown(&parent_span) => {
+                self.add_waiting_span(&parent_span, pending);
+            }
+            Some(parent_span)
+                if self.child_queues.contains_key(&parent_span)
+                    || !self.has_free_child_slot(&parent_span) =>
+            {
+                self.add_pending_child(&parent_span, pending, &bar.order_path, bar.level);
+            }
+            _ => self.add_pending_pb(pending),
+        }
+    }
+
+    fn is_shown(&self, span_id: &span::Id) -> bool {
+        self.visible_bars.iter().any(|bar| &bar.span_id == span_id)
+    }
+
+    // Lingering progress bars cannot be hidden, so their ancestors must stay shown to keep them
+    // drawn below their parent.
+    fn has_lingering_descendants(&self, span_id: &span::Id) -> bool {
+        self.lingering_bars
+            .iter()
+            .any(|bar| bar.order_path.iter().any(|entry| &entry.span_id == span_id))
+    }
+
+    // Queues a span until the progress bar of its parent is shown, see `promote_waiting_spans`.
+    fn add_waiting_span(&mut self, parent_span: &span::Id, pending: PendingSpan) {
+        self.waiting_spans
+            .entry(parent_span.clone())
+            .or_default()
+            .push(pending);
+        self.increment_pending_pb();
+    }
+
+    // Removes a span that closed while waiting for its parent. The caller updates the pending
+    // count.
+    fn remove_waiting_span(&mut self, parent_span: &span::Id, span_id: &span::Id) {
+        let Some(waiting_spans) = self.waiting_spans.get_mut(parent_span) else {
+            return;
+        };
+
+        waiting_spans.retain(|pending| &pending.span_id != span_id);
+
+        if waiting_spans.is_empty() {
+            self.waiting_spans.remove(parent_span);
+        }
+    }
+
+    // Moves the spans waiting for the given span, whose progress bar was just shown, into the
+    // pending queue, where they come before all others.
+    fn promote_waiting_spans(&mut self, span_id: &span::Id) {
+        if let Some(waiting_spans) = self.waiting_spans.remove(span_id) {
+            self.pending_spans
+                .extend(waiting_spans.into_iter().map(|pending| PendingSpan {
+                    with_parent: true,
+                    ..pending
+                }));
+        }
+    }
+
+    // Numbers a span that is queued, see `PendingSpan::queued`.
+    fn next_queued(&mut self, evicted: bool) -> i64 {
+        self.queued_spans += 1;
+
+        if evicted {
+            -self.queued_spans
+        } else {
+            self.queued_spans
+        }
+    }
+
+    fn visible_children(&self, parent_span: &span::Id) -> u64 {
+        self.visible_bars
+            .iter()
+            .filter(|bar| bar.parent_span.as_ref() == Some(parent_span))
+            .count() as u64
+    }
+
+    fn has_free_child_slot(&self, parent_span: &span::Id) -> bool {
+        self.max_child_progress_bars
+            .is_none_or(|max| self.visible_children(parent_span) < max)
+    }
+
+    // Queues a child span until its parent has a free child slot, showing the footer of the parent
+    // if there is one. `order_path` and `level` are those of the child span.
+    fn add_pending_child(
+        &mut self,
+        parent_span: &span::Id,
+        pending: PendingSpan,
+        order_path: &[OrderEntry],
+        level: u16,
+    ) {
+        let queue = self
+            .child_queues
+            .entry(parent_span.clone())
+            .or_insert_with(|| ChildQueue {
+                pending_spans: BinaryHeap::new(),
+                pending_progress_bars: Arc::new(AtomicUsize::new(0)),
+                order_path: order_path[..order_path.len().saturating_sub(1)].to_vec(),
+                level,
+                footer_pb: None,
+            });
+
+        queue.pending_spans.push(pending);
+        self.update_child_footer(parent_span);
+    }
+
+    // Removes a child span that closed while waiting for a free child slot. Returns whether the
+    // span was waiting.
+    fn remove_pending_child(&mut self, parent_span: &span::Id, span_id: &span::Id) -> bool {
+        let Some(queue) = self.child_queues.get_mut(parent_span) else {
+            return false;
+        };
+
+        let len = queue.pending_spans.len();
+        queue
+            .pending_spans
+            .retain(|pending| &pending.span_id != span_id);
+
+        if queue.pending_spans.len() == len {
+            return false;
+        }
+
+        self.update_child_footer(parent_span);
+        true
+    }
+
+    // Updates the footer of a parent after its queue changed, removing the queue once empty.
+    fn update_child_footer(&mut self, parent_span: &span::Id) {
+        let Some(queue) = self.child_queues.get_mut(parent_span) else {
+            return;
+        };
+
+        queue.pending_progress_bars.store(
+            queue.pending_spans.len(),
+            std::sync::atomic::Ordering::Release,
+        );
+
+        if queue.pending_spans.is_empty() {
+            if let Some(footer_pb) = queue.footer_pb.take() {
+                footer_pb.finish_and_clear();
+                self.mp.remove(&footer_pb);
+            }
+
+            self.child_queues.remove(parent_span);
+            return;
+        }
+
+        if let Some(ref footer_pb) = queue.footer_pb {
+            footer_pb.tick();
+            return;
+        }
+
+        let Some(ref footer_style) = self.child_footer_style else {
+            return;
+        };
+
+        let footer_pb = ProgressBar::hidden()
+            .with_style(queue.footer_style(footer_style, &self.style_settings));
+
+        let order_path = queue.order_path.clone();
+        let footer_pb = self.insert_at(Position::footer(&order_path), footer_pb);
+
+        if let Some(tick_interval) = self.tick_settings.footer_tick_interval {
+            footer_pb.enable_steady_tick(tick_interval);
+        }
+
+        footer_pb.tick();
+
+        if let Some(queue) = self.child_queues.get_mut(parent_span) {
+            queue.footer_pb = Some(footer_pb);
+        }
+    }
+
+    /// Shows pending progress bars for as long as there are free slots, e.g. after the maximum
+    /// number of progress bars was increased through an `IndicatifHandle`.
+    pub(crate) fn show_pending_progress_bars(&mut self) {
+        let Some(dispatch) = self.dispatch.as_ref().and_then(WeakDispatch::upgrade) else {
+            return;
+        };
+
+        if let Some(ctx) = dispatch.downcast_ref::<WithContext>() {
+            self.promote_pending_progress_bars(|span_id, f| {
+                ctx.with_context(&dispatch, span_id, f);
+            });
+        }
+    }
+
+    pub(crate) fn set_draw_target(&mut self, draw_target: DrawTarget) {
+        self.draw_target = draw_target;
+        self.apply_output_settings();
+    }
+
+    pub(crate) fn set_output_mode(&mut self, output_mode: OutputMode) {
+        self.output_mode = output_mode;
+        self.apply_output_settings();
+    }
+
+    pub(crate) fn text_output(&self) -> Option<Arc<TextOutput>> {
+        self.text_output.clone()
+    }
+
+    pub(crate) fn set_json_events(&mut self, json_events: Option<Arc<JsonEvents>>) {
+        self.json_events = json_events;
+    }
+
+    pub(crate) fn json_events(&self) -> Option<Arc<JsonEvents>> {
+        self.json_events.clone()
+    }
+
+    fn apply_output_settings(&mut self) {
+        let output_mode = match self.output_mode {
+            OutputMode::Auto if self.draw_target.is_unattended() => OutputMode::Text,
+            OutputMode::Auto => OutputMode::ProgressBars,
+            output_mode => output_mode,
+        };
+
+        self.text_output = None;
+        self.heartbeat_output = None;
+
+        if output_mode == OutputMode::ProgressBars {
+            if self
+                .rendering_enabled
+                .load(std::sync::atomic::Ordering::Acquire)
+            {
+                self.mp.set_draw_target(
+                    self.draw_target
+                        .to_progress_draw_target(self.tick_settings.term_draw_hz),
+                );
+            } else {
+                self.mp.set_draw_target(ProgressDrawTarget::hidden());
+            }
+
+            return;
+        }
+
+        // Progress bars are still managed as usual, they are just never drawn.
+        self.mp.set_draw_target(ProgressDrawTarget::hidden());
+
+        let output = TextOutput::new(
+            self.mp.clone(),
+            self.draw_target.clone(),
+            self.tick_settings.text_line_interval,
+            self.rendering_enabled.clone(),
+        );
+
+        if output_mode == OutputMode::Heartbeat {
+            self.heartbeat_output = Some(output);
+        } else {
+            self.text_output = Some(Arc::new(output));
+        }
+    }
+
+    pub(crate) fn set_show_delay(&mut self, show_delay: Duration) {
+        self.show_delay = show_delay;
+    }
+
+    pub(crate) fn set_min_display_time(
+        &mut self,
+        min_display_time: Duration,
+        done_style: Option<ProgressStyle>,
+    ) {
+        self.min_display_time = min_display_time;
+        self.done_style = done_style;
+    }
+
+    pub(crate) fn set_rotation_interval(&mut self, rotation_interval: Option<Duration>) {
+        self.rotation_interval = rotation_interval;
+    }
+
+    pub(crate) fn set_progress_bar_order(&mut self, order: ProgressBarOrder) {
+        self.order = Some(order);
+    }
+
+    /// Returns the position of a span among the spans with the same parent, for a span whose
+    /// progress bar is started right now.
+    pub(crate) fn order_entry(&mut self, pb_span_ctx: &IndicatifSpanContext) -> OrderEntry {
+        let key = match self.order.unwrap_or_default() {
+            ProgressBarOrder::Creation => OrderKey::Creation,
+            ProgressBarOrder::StartTime => {
+                self.started_progress_bars += 1;
+                OrderKey::Started(self.started_progress_bars)
+            }
+            ProgressBarOrder::Name => OrderKey::Name(pb_span_ctx.span_name.clone()),
+            ProgressBarOrder::Field(_) => OrderKey::Field(
+                pb_span_ctx.order_field.is_none(),
+                pb_span_ctx.order_field.clone(),
+            ),
+            ProgressBarOrder::Eta => OrderKey::Eta,
+        };
+
+        OrderEntry {
+            span_id: pb_span_ctx.span_id.clone(),
+            created: pb_span_ctx.created,
+            pinned: pb_span_ctx.pinned,
+            key,
+        }
+    }
+
+    // Unless an order is set, top level progress bars are drawn below the others as they are shown,
+    // including when shown from the pending queue. Pinned progress bars still come first.
+    fn place_top_level_progress_bar(&mut self, pb_span_ctx: &mut IndicatifSpanContext) {
+        if self.order.is_some() || pb_span_ctx.parent_span.is_some() || pb_span_ctx.pinned {
+            return;
+        }
+
+        if let Some(entry) = pb_span_ctx.order_path.first_mut() {
+            self.shown_progress_bars += 1;
+            entry.key = OrderKey::Shown(self.shown_progress_bars);
+        }
+    }
+
+    fn cmp_positions(&self, a: Position<'_>, b: Position<'_>) -> std::cmp::Ordering {
+        order::cmp_positions(a, b, |span_id| {
+            self.visible_bars
+                .iter()
+                .find(|bar| &bar.span_id == span_id)
+                .and_then(VisibleBar::eta)
+        })
+    }
+
+    // Returns the shown, child footer or lingering progress bar that comes right after the given
+    // position, i.e. the one to insert before, if any.
+    fn next_pb(&self, position: Position<'_>) -> Option<&ProgressBar> {
+        let next_bar = self
+            .visible_bars
+            .iter()
+            .find(|bar| {
+                self.cmp_positions(position, Position::bar(&bar.order_path))
+                    .is_lt()
+            })
+            .map(|bar| (Position::bar(&bar.order_path), &bar.pb));
+        let next_other = self
+            .other_positions()
+            .filter(|&(other, _)| self.cmp_positions(position, other).is_lt())
+            .min_by(|&(a, _), &(b, _)| self.cmp_positions(a, b));
+
+        match (next_bar, next_other) {
+            (Some(bar), Some(other)) if self.cmp_positions(other.0, bar.0).is_lt() => Some(other.1),
+            (Some(bar), _) => Some(bar.1),
+            (None, other) => other.map(|(_, pb)| pb),
+        }
+    }
+
+    // The progress bars drawn between the shown progress bars: child footers and lingering
+    // progress bars.
+    fn other_positions(&self) -> impl Iterator<Item = (Position<'_>, &ProgressBar)> {
+        self.child_queues
+            .values()
+            .filter_map(|queue| {
+                Some((
+                    Position::footer(&queue.order_path),
+                    queue.footer_pb.as_ref()?,
+                ))
+            })
+            .chain(
+                self.lingering_bars
+                    .iter()
+                    .map(|bar| (Position::bar(&bar.order_path), &bar.pb)),
+            )
+    }
+
+    // Adds a progress bar at the given position, i.e. right before the progress bar or child
+    // footer that comes after it.
+    fn insert_at(&self, position: Position<'_>, pb: ProgressBar) -> ProgressBar {
+        match self.next_pb(position) {
+            Some(next_pb) => self.mp.insert_before(next_pb, pb),
+            None => self.add_to_end(pb),
+        }
+    }
+
+    // Adds a progress bar after all others, but before the footer.
+    fn add_to_end(&self, pb: ProgressBar) -> ProgressBar {
+        if self.footer_is_shown() {
+            self.mp.insert_from_back(1, pb)
+        } else {
+            self.mp.add(pb)
+        }
+    }
+
+    // Re-sorts shown progress bars whose order changes over time, see `ProgressBarOrder::Eta`.
+    fn sort_progress_bars(&mut self, now: Instant) {
+        if self.order != Some(ProgressBarOrder::Eta)
+            || now.duration_since(self.last_sort) < ETA_SORT_INTERVAL
+        {
+            return;
+        }
+
+        self.last_sort = now;
+
+        let mut order = (0..self.visible_bars.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            self.cmp_positions(
+                Position::bar(&self.visible_bars[a].order_path),
+                Position::bar(&self.visible_bars[b].order_path),
+            )
+        });
+
+        if order
+            .iter()
+            .enumerate()
+            .all(|(idx, &bar_idx)| idx == bar_idx)
+        {
+            return;
+        }
+
+        // Progress bars cannot be moved within a `MultiProgress`, so we re-add all of them, along
+        // with the child footers and lingering progress bars in between.
+        for bar in &self.visible_bars {
+            self.mp.remove(&bar.pb);
+
+            for line_pb in &bar.log_tail {
+                self.mp.remove(line_pb);
+            }
+        }
+
+        for (_, other_pb) in self.other_positions() {
+            self.mp.remove(other_pb);
+        }
+
+        let mut visible_bars = std::mem::take(&mut self.visible_bars)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.visible_bars = order
+            .into_iter()
+            .filter_map(|bar_idx| visible_bars[bar_idx].take())
+            .collect();
+
+        let mut others = self.other_positions().collect::<Vec<_>>();
+        others.sort_by(|&(a, _), &(b, _)| self.cmp_positions(a, b));
+        let mut others = others.into_iter().peekable();
+
+        for bar in &self.visible_bars {
+            while let Some((_, other_pb)) = others.next_if(|&(other, _)| {
+                self.cmp_positions(other, Position::bar(&bar.order_path))
+                    .is_lt()
+            }) {
+                self.add_to_end(other_pb.clone());
+            }
+
+            self.add_to_end(bar.pb.clone());
+
+            for line_pb in &bar.log_tail {
+                self.add_to_end(line_pb.clone());
+            }
+        }
+
+        for (_, other_pb) in others {
+            self.add_to_end(other_pb.clone());
+        }
+    }
+
+    // Whether progress bars are drawn, as opposed to progress being reported as plain text.
+    fn draws_progress_bars(&self) -> bool {
+        self.text_output.is_none() && self.heartbeat_output.is_none()
+    }
+
+    fn needs_background_ticks(&self) -> bool {
+        self.heartbeat_output.is_some()
+            || !self.delayed_spans.is_empty()
+            || !self.lingering_bars.is_empty()
+            || self.sorts_progress_bars()
+            || self.rotates_progress_bars()
+    }
+
+    // Whether there are shown progress bars to re-sort, see `sort_progress_bars`.
+    fn sorts_progress_bars(&self) -> bool {
+        self.order == Some(ProgressBarOrder::Eta) && !self.visible_bars.is_empty()
+    }
+
+    // Whether there are pending progress bars to rotate in, see `rotate_progress_bars`.
+    fn rotates_progress_bars(&self) -> bool {
+        self.rotation_interval.is_some()
+            && (self
+                .pending_progress_bars
+                .load(std::sync::atomic::Ordering::Acquire)
+                > 0
+                || !self.child_queues.is_empty())
+    }
+
+    /// Makes sure periodic work is performed if there is any (e.g. printing heartbeats or showing
+    /// delayed progress bars), spawning the background ticker for the given `ProgressBarManager`
+    /// (i.e. `self`) or waking it up so it notices new work.
+    pub(crate) fn ensure_background_ticker(&mut self, pb_manager: Weak<Mutex<ProgressBarManager>>) {
+        if !self.needs_background_ticks() {
+            return;
+        }
+
+        match self.background_ticker {
+            Some(ref background_ticker) => background_ticker.unpark(),
+            None => self.background_ticker = spawn_background_ticker(pb_manager),
+        }
+    }
+
+    // Performs any periodic work, returning how long until this should be called again, or `None`
+    // if there is no more work to do.
+    fn background_tick(&mut self, now: Instant) -> Option<Duration> {
+        let mut wait = None;
+
+        if let Some(ref heartbeat_output) = self.heartbeat_output {
+            let heartbeat_interval = self.tick_settings.heartbeat_interval;
+
+            if now.duration_since(self.last_heartbeat) >= heartbeat_interval {
+                self.last_heartbeat = now;
+
+                if !self.visible_bars.is_empty() {
+                    for line in format_heartbeat(
+                        &self.visible_bars,
+                        self.pending_progress_bars
+                            .load(std::sync::atomic::Ordering::Acquire),
+                    ) {
+                        heartbeat_output.write_line(&line);
+                    }
+                }
+            }
+
+            wait = Some(heartbeat_interval.saturating_sub(now.duration_since(self.last_heartbeat)));
+        }
+
+        self.remove_lingering_progress_bars(now);
+        self.show_delayed_progress_bars(now);
+        self.rotate_progress_bars(now);
+        self.sort_progress_bars(now);
+
+        let next_sort = self
+            .sorts_progress_bars()
+            .then(|| self.last_sort + ETA_SORT_INTERVAL);
+        let next_rotation = self
+            .rotation_interval
+            .filter(|_| self.rotates_progress_bars())
+            .and_then(|rotation_interval| {
+                self.visible_bars
+                    .iter()
+                    .filter(|bar| !bar.pinned)
+                    .map(|bar| bar.shown_at + rotation_interval)
+                    .min()
+            });
+
+        self.delayed_spans
+            .iter()
+            .map(|(_, eligible_at)| *eligible_at)
+            .chain(self.lingering_bars.iter().map(|bar| bar.remove_at))
+            .chain(next_sort)
+            .chain(next_rotation)
+            .map(|instant| instant.saturating_duration_since(now))
+            .chain(wait)
+            .min()
+    }
+
+    // Removes lingering progress bars whose minimum display time has passed, showing pending
+    // progress bars in their place.
+    fn remove_lingering_progress_bars(&mut self, now: Instant) {
+        let len = self.lingering_bars.len();
+
+        self.lingering_bars.retain(|bar| {
+            if bar.remove_at > now {
+                return true;
+            }
+
+            bar.pb.finish_and_clear();
+            self.mp.remove(&bar.pb);
+            false
+        });
+
+        let removed = len - self.lingering_bars.len();
+
+        if removed > 0 {
+            self.active_progress_bars -= removed as u64;
+            self.show_pending_progress_bars();
+        }
+    }
+
+    // Shows (or queues) the progress bars of delayed spans that have been alive long enough.
+    fn show_delayed_progress_bars(&mut self, now: Instant) {
+        if !self
+            .delayed_spans
+            .iter()
+            .any(|(_, eligible_at)| *eligible_at <= now)
+        {
+            return;
+        }
+
+        let Some(dispatch) = self.dispatch.as_ref().and_then(WeakDispatch::upgrade) else {
+            return;
+        };
+
+        let Some(ctx) = dispatch.downcast_ref::<WithContext>() else {
+            return;
+        };
+
+        let (eligible, delayed) = std::mem::take(&mut self.delayed_spans)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, eligible_at)| *eligible_at <= now);
+        self.delayed_spans = delayed;
+
+        for (span_id, _) in eligible {
+            ctx.with_context(&dispatch, &span_id, |pb_span_ctx| {
+                // The span may be closing, see `promote_pending_progress_bars`.
+                if pb_span_ctx.progress_bar.is_some() {
+                    pb_span_ctx.start_reporting(self);
+                    self.show_progress_bar(pb_span_ctx, &span_id);
+                }
+            });
+        }
+
+        // Children of the progress bars just shown may be waiting.
+        self.promote_pending_progress_bars(|span_id, f| {
+            ctx.with_context(&dispatch, span_id, f);
+        });
+    }
+
+    fn footer_is_shown(&self) -> bool {
+        self.footer_pb.is_some()
+            && self
+                .pending_progress_bars
+                .load(std::sync::atomic::Ordering::Acquire)
+                > 0
+    }
+
+    fn show_footer(&self) {
+        if let Some(footer_pb) = self.footer_pb.as_ref() {
+            footer_pb.reset();
+
+            if let Some(tick_interval) = self.tick_settings.footer_tick_interval {
+                footer_pb.enable_steady_tick(tick_interval);
+            }
+
+            self.mp.add(footer_pb.clone());
+            // Appears to have broken with
+            // https://github.com/console-rs/indicatif/pull/648
+            // self.mp.set_move_cursor(true);
+        }
+    }
+
+    fn hide_footer(&self) {
+        if let Some(footer_pb) = self.footer_pb.as_ref() {
+            if self.tick_settings.footer_tick_interval.is_some() {
+                footer_pb.disable_steady_tick();
+            }
+
+            // Appears to have broken with
+            // https://github.com/console-rs/indicatif/pull/648
+            // self.mp.set_move_cursor(false);
+            footer_pb.finish_and_clear();
+            self.mp.remove(footer_pb);
+        }
+    }
+
+    fn decrement_pending_pb(&mut self) {
+        let prev_val = self
+            .pending_progress_bars
+            .fetch_sub(1, std::sync::atomic::Ordering::AcqRel);
+
+        // If this span was the last one pending, clear the footer (if it was active).
+        if prev_val == 1 {
+            self.hide_footer();
+        } else if let Some(footer_pb) = self.footer_pb.as_ref() {
+            footer_pb.tick();
+        }
+    }
+
+    fn add_pending_pb(&mut self, pending: PendingSpan) {
+        self.pending_spans.push(pending);
+        self.increment_pending_pb();
+    }
+
+    fn increment_pending_pb(&mut self) {
+        let prev_val = self
+            .pending_progress_bars
+            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+
+        // Show the footer progress bar.
+        if prev_val == 0 {
+            self.show_footer();
+        }
+
+        if let Some(footer_pb) = self.footer_pb.as_ref() {
+            footer_pb.tick();
+        }
+    }
+
+    /// Shows (or queues) the progress bar of a span that was just entered for the first time, once
+    /// the span has been alive for its show delay. Returns whether the background ticker must be
+    /// notified, e.g. because the progress bar is delayed.
+    pub(crate) fn start_progress_bar(
+        &mut self,
+        pb_span_ctx: &mut IndicatifSpanContext,
+        span_id: &span::Id,
+    ) -> bool {
+        let show_delay = pb_span_ctx.show_delay.unwrap_or(self.show_delay);
+        let mut eligible_at = (!show_delay.is_zero()).then(|| Instant::now() + show_delay);
+
+        // Children of a progress bar that is not eligible yet become eligible along with it, rather
+        // than waiting for it as pending. The parent comes first in `delayed_spans`, so it is shown
+        // first.
+        let parent_eligible_at = pb_span_ctx.parent_span.as_ref().and_then(|parent_span| {
+            self.delayed_spans
+                .iter()
+                .find(|(delayed_span_id, _)| delayed_span_id == parent_span)
+                .map(|(_, parent_eligible_at)| *parent_eligible_at)
+        });
+
+        if let Some(parent_eligible_at) = parent_eligible_at {
+            eligible_at = Some(eligible_at.map_or(parent_eligible_at, |eligible_at| {
+                eligible_at.max(parent_eligible_at)
+            }));
+        }
+
+        let Some(eligible_at) = eligible_at else {
+            pb_span_ctx.start_reporting(self);
+            self.show_progress_bar(pb_span_ctx, span_id);
+            return self.needs_background_ticks();
+        };
+
+        self.delayed_spans.push((span_id.clone(), eligible_at));
+
+        true
+    }
+
+    pub(crate) fn show_progress_bar(
+        &mut self,
+        pb_span_ctx: &mut IndicatifSpanContext,
+        span_id: &span::Id,
+    ) {
+        // Children are only shown along with their parent, even if they are pinned.
+        if let Some(ref parent_span) = pb_span_ctx.parent_span {
+            if !self.is_shown(parent_span) {
+                let pending = PendingSpan {
+                    with_parent: false,
+                    priority: pb_span_ctx.priority,
+                    queued: self.next_queued(false),
+                    span_id: span_id.clone(),
+                };
+                self.add_waiting_span(parent_span, pending);
+
+                if let Some(ref pb) = pb_span_ctx.progress_bar {
+                    pb_span_ctx.report_json_event(JsonEvent::Pending, pb);
+                }
+
+                return;
+            }
+
+            // The position of the parent is only decided once it is shown, see
+            // `place_top_level_progress_bar`.
+            if let Some(parent_bar) = self
+                .visible_bars
+                .iter()
+                .find(|bar| &bar.span_id == parent_span)
+            {
+                let level = pb_span_ctx.order_path.len().saturating_sub(1);
+                pb_span_ctx
+                    .order_path
+                    .splice(..level, parent_bar.order_path.iter().cloned());
+            }
+        }
+
+        // Children wait for a free child slot of their parent next. Pinned progress bars are
+        // shown regardless.
+        if !pb_span_ctx.pinned {
+            if let Some(ref parent_span) = pb_span_ctx.parent_span {
+                if !self.has_free_child_slot(parent_span) {
+                    let pending = PendingSpan {
+                        with_parent: false,
+                        priority: pb_span_ctx.priority,
+                        queued: self.next_queued(false),
+                        span_id: span_id.clone(),
+                    };
+                    self.add_pending_child(
+                        parent_span,
+                        pending,
+                        &pb_span_ctx.order_path,
+                        pb_span_ctx.level,
+                    );
+
+                    if let Some(ref pb) = pb_span_ctx.progress_bar {
+                        pb_span_ctx.report_json_event(JsonEvent::Pending, pb);
+                    }
+
+                    return;
+                }
+            }
+        }
+
+        // Pinned progress bars are shown even if there are no free slots.
+        if pb_span_ctx.pinned && self.active_progress_bars >= self.max_progress_bars {
+            self.evict_progress_bar(&pb_span_ctx.order_path);
+        }
+
+        if self.active_progress_bars < self.max_progress_bars || pb_span_ctx.pinned {
+            let Some(pb) = pb_span_ctx.progress_bar.take() else {
+                return;
+            };
+
+            self.place_top_level_progress_bar(pb_span_ctx);
+
+            // Progress bars take precedence over log tail lines.
+            while self.active_progress_bars + self.shown_log_tail_lines >= self.max_progress_bars
+                && self.hide_log_tail_line()
+            {}
+
+            // Shown right before the progress bar that comes after it, i.e. after the log tail of
+            // the progress bar that comes before it.
+            let position = Position::bar(&pb_span_ctx.order_path);
+            let visible_bar_idx = self
+                .visible_bars
+                .iter()
+                .position(|bar| {
+                    self.cmp_positions(position, Position::bar(&bar.order_path))
+                        .is_lt()
+                })
+                .unwrap_or(self.visible_bars.len());
+            let pb = self.insert_at(position, pb);
+
+            self.active_progress_bars += 1;
+            self.visible_bars.insert(
+                visible_bar_idx,
+                VisibleBar {
+                    span_id: span_id.clone(),
+                    pb: pb.clone(),
+                    span_child_prefix: pb_span_ctx.span_child_prefix.clone(),
+                    label: pb_span_ctx.text_label(),
+                    timer: pb_span_ctx.timer.clone(),
+                    log_tail: Vec::new(),
+                    log_tail_lines: Vec::new(),
+                    level: pb_span_ctx.level,
+                    shown_at: Instant::now(),
+                    order_path: pb_span_ctx.order_path.clone(),
+                    priority: pb_span_ctx.priority,
+                    pinned: pb_span_ctx.pinned,
+                    parent_span: pb_span_ctx.parent_span.clone(),
+                },
+            );
+
+            if let Some(tick_interval) = self.tick_settings.default_tick_interval {
+                pb.enable_steady_tick(tick_interval);
+            }
+
+            pb.tick();
+
+            pb_span_ctx.report_json_event(JsonEvent::Shown, &pb);
+            pb_span_ctx.progress_bar = Some(pb);
+
+            // Lines may have been logged while the progress bar was pending.
+            self.show_log_tail(pb_span_ctx);
+
+            // Children that waited for this progress bar are shown next.
+            self.promote_waiting_spans(span_id);
+        } else {
+            let pending = PendingSpan {
+                with_parent: false,
+                priority: pb_span_ctx.priority,
+                queued: self.next_queued(false),
+                span_id: span_id.clone(),
+            };
+            self.add_pending_pb(pending);
+
+            if let Some(ref pb) = pb_span_ctx.progress_bar {
+                pb_span_ctx.report_json_event(JsonEvent::Pending, pb);
+            }
+        }
+    }
+
+    /// Finishes the progress bar of a closed span. Returns whether the progress bar lingers (see
+    /// `IndicatifLayer::with_min_display_time`), in which case the background ticker must be
+    /// notified.
+    pub(crate) fn finish_progress_bar<S>(
+        &mut self,
+        pb_span_ctx: &mut IndicatifSpanContext,
+        span_id: &span::Id,
+        ctx: &layer::Context<'_, S>,
+    ) -> bool
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(pb) = pb_span_ctx.progress_bar.take() else {
+            // Span was never entered.
+            return false;
+        };
+
+        pb_span_ctx.report_text_finished(&pb);
+        self.flush_log_tail(pb_span_ctx);
+
+        // The span closed before we had a chance to show its progress bar.
+        let Some(visible_bar_idx) = self
+            .visible_bars
+            .iter()
+            .position(|bar| &bar.span_id == span_id)
+        else {
+            // Delayed progress bars were never queued, children may wait for their parent.
+            if let Some(delayed_idx) = self
+                .delayed_spans
+                .iter()
+                .position(|(delayed_span_id, _)| delayed_span_id == span_id)
+            {
+                self.delayed_spans.swap_remove(delayed_idx);
+            } else if !pb_span_ctx
+                .parent_span
+                .as_ref()
+                .is_some_and(|parent_span| self.remove_pending_child(parent_span, span_id))
+            {
+                // Spans waiting for their parent count as pending as well.
+                if let Some(ref parent_span) = pb_span_ctx.parent_span {
+                    self.remove_waiting_span(parent_span, span_id);
+                }
+
+                self.decrement_pending_pb();
+            }
+
+            pb_span_ctx.report_json_event(JsonEvent::Cleared, &pb);
+            return false;
+        };
+
+        let bar = self.visible_bars.remove(visible_bar_idx);
+
+        for line_pb in bar.log_tail {
+            line_pb.finish_and_clear();
+            self.mp.remove(&line_pb);
+            self.shown_log_tail_lines -= 1;
+        }
+
+        // This span had an active/shown progress bar.
+        if let Some(finish_message) = pb_span_ctx.finish_message.take() {
+            pb.finish_with_message(finish_message);
+            pb_span_ctx.report_json_event(JsonEvent::Finished, &pb);
+        } else if self.draws_progress_bars() && bar.shown_at.elapsed() < self.min_display_time {
+            // Keep the final state shown, the progress bar keeps its slot until it is removed.
+            if let Some(ref done_style) = self.done_style {
+                pb.set_style(pb_span_ctx.add_keys_to_style(done_style.clone()));
+            }
+
+            pb.finish();
+            pb_span_ctx.report_json_event(JsonEvent::Cleared, &pb);
+            self.lingering_bars.push(LingeringBar {
+                pb,
+                remove_at: bar.shown_at + self.min_display_time,
+                order_path: bar.order_path,
+            });
+
+            return true;
+        } else {
+            pb.finish_and_clear();
+            self.mp.remove(&pb);
+            pb_span_ctx.report_json_event(JsonEvent::Cleared, &pb);
+        }
+        self.active_progress_bars -= 1;
+
+        self.promote_pending_progress_bars(|span_id, f| {
+            // Span was closed earlier, we "garbage collect" it from the queue here.
+            if let Some(span) = ctx.span(span_id) {
+                let mut ext = span.extensions_mut();
+                let indicatif_span_ctx = ext
+                    .get_mut::<IndicatifSpanContext>()
+                    .expect("No IndicatifSpanContext found; this is a bug");
+
+                f(indicatif_span_ctx);
+            }
+        });
+
+        false
+    }
+
+    // Prints the log tail of a failed span above the progress bars, so it is not lost once the
+    // progress bar is gone. This writes to the draw target directly, so the lines are not lost
+    // while rendering is disabled either.
+    fn flush_log_tail(&self, pb_span_ctx: &mut IndicatifSpanContext) {
+        if !pb_span_ctx.failed {
+            return;
+        }
+
+        for line in pb_span_ctx.log_tail.drain(..) {
+            self.draw_target.write_line(&self.mp, &line);
+        }
+    }
+
+    // Shows pending progress bars, then hidden log tail lines, while there are free slots.
+    // `with_span_ctx` must call the given function with the context of the given span, if that
+    // span still exists.
+    fn promote_pending_progress_bars(
+        &mut self,
+        mut with_span_ctx: impl FnMut(&span::Id, &mut dyn FnMut(&mut IndicatifSpanContext)),
+    ) {
+        while self.active_progress_bars < self.max_progress_bars {
+            let Some((PendingSpan { span_id, .. }, from_child_queue)) = self.pop_pending_span()
+            else {
+                break;
+            };
+
+            with_span_ctx(&span_id, &mut |indicatif_span_ctx| {
+                // It possible `on_close` has been called on a span but it has not yet been
+                // removed from `ctx.span` (e.g., tracing may still be iterating through each
+                // layer's `on_close` method and cannot remove the span from the registry until
+                // it has finished `on_close` for each layer). So we may successfully fetch the
+                // span, despite having closed out its progress bar.
+                if indicatif_span_ctx.progress_bar.is_none() {
+                    return;
+                }
+
+                if !from_child_queue {
+                    self.decrement_pending_pb();
+                }
+
+                self.show_progress_bar(indicatif_span_ctx, &span_id);
+            });
+        }
+
+        // Log tail lines only take the slots no progress bar needs.
+        self.show_hidden_log_tail_lines();
+    }
+
+    // Pops the pending span to show next: the one with the highest priority among the global
+    // queue and the children whose parent has a free child slot. Returns whether it was a child.
+    fn pop_pending_span(&mut self) -> Option<(PendingSpan, bool)> {
+        let child_queue = self
+            .child_queues
+            .iter()
+            .filter(|(parent_span, _)| self.has_free_child_slot(parent_span))
+            .filter_map(|(parent_span, queue)| Some((parent_span, queue.pending_spans.peek()?)))
+            .max_by(|(_, a), (_, b)| a.cmp(b))
+            .filter(|(_, pending)| {
+                self.pending_spans
+                    .peek()
+                    .is_none_or(|global| *pending > global)
+            })
+            .map(|(parent_span, _)| parent_span.clone());
+
+        let Some(parent_span) = child_queue else {
+            return self.pending_spans.pop().map(|pending| (pending, false));
+        };
+
+        let pending = self
+            .child_queues
+            .get_mut(&parent_span)
+            .and_then(|queue| queue.pending_spans.pop());
+        self.update_child_footer(&parent_span);
+
+        pending.map(|pending| (pending, true))
+    }
+}
+
+/// Spawns a thread that performs periodic work for the given [`ProgressBarManager`], e.g. printing
+/// heartbeats. The thread exits once the `ProgressBarManager` is dropped or there is no more work
+/// to do, and can be woken up early by unparking it.
+fn spawn_background_ticker(pb_manager: Weak<Mutex<ProgressBarManager>>) -> Option<thread::Thread> {
+    thread::Builder::new()
+        .name("tracing-indicatif".to_string())
+        .spawn(move || {
+            loop {
+                let Some(pb_manager) = pb_manager.upgrade() else {
+                    return;
+                };
+
+                let wait = match pb_manager.lock() {
+                    Ok(mut pb_manager) => match pb_manager.background_tick(Instant::now()) {
+                        Some(wait) => wait,
+                        None => {
+                            pb_manager.background_ticker = None;
+                            return;
+                        }
+                    },
+                    Err(_) => return,
+                };
+
+                drop(pb_manager);
+                thread::park_timeout(wait);
+            }
+        })
+        .ok()
+        .map(|join_handle| join_handle.thread().clone())
 }
@@ -3,6 +3,7 @@ use indicatif::ProgressStyle;
 use std::time::Duration;
 use tracing::Span;
 
+use crate::ChildProgress;
 use crate::IndicatifSpanContext;
 use crate::WithContext;
 
@@ -93,6 +94,35 @@ pub trait IndicatifSpanExt {
     /// If unset, the progress bar will be removed when the span is finished.
     fn pb_set_finish_message(&self, msg: &str);
 
+    /// Sets how long this span must be alive before its progress bar is shown, overriding
+    /// [`IndicatifLayer::with_show_delay`](crate::IndicatifLayer::with_show_delay).
+    ///
+    /// Has no effect if this span has already been entered before.
+    fn pb_set_show_delay(&self, show_delay: Duration);
+
+    /// Derives the length and position of the progress bar for this span from its child spans,
+    /// overriding [`IndicatifLayer::with_child_progress`](crate::IndicatifLayer::with_child_progress).
+    /// Only child spans created afterwards count. See [`ChildProgress`].
+    fn pb_set_child_progress(&self, child_progress: ChildProgress);
+
+    /// Sets how many times this span counts towards the progress of its parent, if the parent
+    /// derives its progress from its children. Defaults to 1. See [`ChildProgress`].
+    fn pb_set_weight(&self, weight: u64);
+
+    /// Sets the priority of the progress bar for this span, overriding
+    /// [`IndicatifLayer::with_priority`](crate::IndicatifLayer::with_priority). Pending progress
+    /// bars with a higher priority are shown first.
+    ///
+    /// Has no effect if this span has already been entered before.
+    fn pb_set_priority(&self, priority: i64);
+
+    /// Pins or unpins the progress bar for this span, overriding
+    /// [`IndicatifLayer::with_pinned`](crate::IndicatifLayer::with_pinned). Pinned progress bars
+    /// never wait in the pending queue.
+    ///
+    /// Has no effect if this span has already been entered before.
+    fn pb_set_pinned(&self, pinned: bool);
+
     /// Returns the current ETA
     fn pb_eta(&self) -> Duration;
 
@@ -172,6 +202,36 @@ impl IndicatifSpanExt for Span {
         });
     }
 
+    fn pb_set_show_delay(&self, show_delay: Duration) {
+        apply_to_indicatif_span(self, |indicatif_ctx| {
+            indicatif_ctx.set_progress_bar_show_delay(show_delay);
+        });
+    }
+
+    fn pb_set_child_progress(&self, child_progress: ChildProgress) {
+        apply_to_indicatif_span(self, |indicatif_ctx| {
+            indicatif_ctx.set_child_progress(child_progress);
+        });
+    }
+
+    fn pb_set_weight(&self, weight: u64) {
+        apply_to_indicatif_span(self, |indicatif_ctx| {
+            indicatif_ctx.set_progress_bar_weight(weight);
+        });
+    }
+
+    fn pb_set_priority(&self, priority: i64) {
+        apply_to_indicatif_span(self, |indicatif_ctx| {
+            indicatif_ctx.set_progress_bar_priority(priority);
+        });
+    }
+
+    fn pb_set_pinned(&self, pinned: bool) {
+        apply_to_indicatif_span(self, |indicatif_ctx| {
+            indicatif_ctx.set_progress_bar_pinned(pinned);
+        });
+    }
+
     fn pb_eta(&self) -> Duration {
         let mut eta: Option<Duration> = None;
         apply_to_indicatif_span(self, |indicatif_ctx| {
@@ -0,0 +1,82 @@
+//! Rules picking the progress bar style of a span, see
+//! [`IndicatifLayer::with_style_rule`](crate::IndicatifLayer::with_style_rule).
+use indicatif::style::ProgressStyle;
+use tracing_core::Metadata;
+use tracing_core::span;
+
+use crate::fields::StyleField;
+
+/// Selects the spans a style rule applies to, see
+/// [`IndicatifLayer::with_style_rule`](crate::IndicatifLayer::with_style_rule).
+#[derive(Clone, Debug)]
+pub struct StyleMatcher {
+    kind: StyleMatcherKind,
+}
+
+#[derive(Clone, Debug)]
+enum StyleMatcherKind {
+    Name(String),
+    TargetPrefix(String),
+    Level(tracing::Level),
+    PbStyle(String),
+}
+
+impl StyleMatcher {
+    /// Matches spans with exactly this name.
+    pub fn name(name: impl Into<String>) -> Self {
+        Self {
+            kind: StyleMatcherKind::Name(name.into()),
+        }
+    }
+
+    /// Matches spans whose target starts with `prefix`, e.g. `myapp::net`.
+    pub fn target_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            kind: StyleMatcherKind::TargetPrefix(prefix.into()),
+        }
+    }
+
+    /// Matches spans at exactly this level.
+    pub fn level(level: tracing::Level) -> Self {
+        Self {
+            kind: StyleMatcherKind::Level(level),
+        }
+    }
+
+    /// Matches spans whose `indicatif.pb_style` field is `style`, e.g.
+    /// `info_span!("fetch", indicatif.pb_style = "download")`.
+    pub fn pb_style(style: impl Into<String>) -> Self {
+        Self {
+            kind: StyleMatcherKind::PbStyle(style.into()),
+        }
+    }
+
+    fn matches(&self, meta: &Metadata<'_>, pb_style: Option<&str>) -> bool {
+        match self.kind {
+            StyleMatcherKind::Name(ref name) => meta.name() == name,
+            StyleMatcherKind::TargetPrefix(ref prefix) => {
+                meta.target().starts_with(prefix.as_str())
+            }
+            StyleMatcherKind::Level(level) => *meta.level() == level,
+            StyleMatcherKind::PbStyle(ref style) => pb_style == Some(style.as_str()),
+        }
+    }
+}
+
+/// Returns the style of the first rule matching the given span, if any.
+pub(crate) fn match_style_rules(
+    style_rules: &[(StyleMatcher, ProgressStyle)],
+    attrs: &span::Attributes<'_>,
+) -> Option<ProgressStyle> {
+    if style_rules.is_empty() {
+        return None;
+    }
+
+    let mut style_field = StyleField::default();
+    attrs.record(&mut style_field);
+
+    style_rules
+        .iter()
+        .find(|(matcher, _)| matcher.matches(attrs.metadata(), style_field.style.as_deref()))
+        .map(|(_, style)| style.clone())
+}
@@ -0,0 +1,242 @@
+//! Utilities to test the progress bars drawn by [`IndicatifLayer`] against an in-memory terminal,
+//! e.g. for snapshot tests.
+//!
+//! Requires the `testing` feature.
+//!
+//! # Example Use
+//!
+//! ```
+//! use tracing::info_span;
+//! use tracing_indicatif::IndicatifLayer;
+//! use tracing_indicatif::style::ProgressStyle;
+//! use tracing_indicatif::testing::TestTerminal;
+//!
+//! let term = TestTerminal::new(10, 80);
+//! let subscriber = term.subscriber(
+//!     IndicatifLayer::new()
+//!         .with_progress_style(ProgressStyle::with_template("{span_name}{{{span_fields}}}").unwrap()),
+//! );
+//!
+//! tracing::subscriber::with_default(subscriber, || {
+//!     let _span = info_span!("foo", val = 3).entered();
+//!
+//!     assert_eq!(term.wait_until_settled(), "foo{val=3}");
+//! });
+//! ```
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use indicatif::InMemoryTerm;
+use indicatif::MultiProgress;
+use indicatif::TermLike;
+use tracing_core::Subscriber;
+use tracing_subscriber::Registry;
+use tracing_subscriber::fmt;
+use tracing_subscriber::fmt::FormatFields;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::DefaultFields;
+use tracing_subscriber::fmt::format::Format;
+use tracing_subscriber::fmt::format::Full;
+use tracing_subscriber::layer::Layered;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::Clock;
+use crate::DrawTarget;
+use crate::IndicatifLayer;
+
+// How long the terminal contents must stay unchanged to be considered settled.
+const SETTLE_DURATION: Duration = Duration::from_millis(100);
+// How long to wait for the terminal contents to settle before giving up.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The subscriber [`TestTerminal::subscriber`] adds the [`IndicatifLayer`] to: a registry with a
+/// `fmt` layer writing logs, without ANSI colors or timestamps, to the [`TestTerminal`].
+pub type TestBaseSubscriber =
+    Layered<fmt::Layer<Registry, DefaultFields, Format<Full, ()>, InMemoryTermWriter>, Registry>;
+
+/// An in-memory terminal of a fixed size that progress bars and logs can be drawn to.
+#[derive(Clone)]
+pub struct TestTerminal {
+    term: InMemoryTerm,
+}
+
+impl TestTerminal {
+    /// Creates a terminal with the given number of rows and columns.
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            term: InMemoryTerm::new(rows, cols),
+        }
+    }
+
+    /// Returns a [`DrawTarget`] drawing to this terminal, to be passed to
+    /// [`IndicatifLayer::with_draw_target`].
+    pub fn draw_target(&self) -> DrawTarget {
+        DrawTarget::term_like(Box::new(self.term.clone()))
+    }
+
+    /// Returns a writer to this terminal that ensures its output will not be clobbered by the
+    /// progress bars of `layer`, e.g. to be passed to
+    /// [`fmt::Layer::with_writer`](tracing_subscriber::fmt::Layer::with_writer).
+    pub fn writer<S, F>(&self, layer: &IndicatifLayer<S, F>) -> InMemoryTermWriter {
+        InMemoryTermWriter {
+            progress_bars: Some(layer.mp.clone()),
+            rendering_enabled: layer.rendering_enabled.clone(),
+            term: self.term.clone(),
+        }
+    }
+
+    /// Builds a subscriber that draws the progress bars of `layer` to this terminal, and writes logs
+    /// to this terminal through a `fmt` layer (without ANSI colors or timestamps).
+    ///
+    /// This overrides the draw target of `layer`.
+    pub fn subscriber<F>(
+        &self,
+        layer: IndicatifLayer<TestBaseSubscriber, F>,
+    ) -> impl Subscriber + use<F>
+    where
+        F: for<'writer> FormatFields<'writer> + 'static,
+    {
+        let layer = layer.with_draw_target(self.draw_target());
+        let writer = self.writer(&layer);
+
+        tracing_subscriber::registry()
+            .with(
+                fmt::layer()
+                    .with_ansi(false)
+                    .without_time()
+                    .with_writer(writer),
+            )
+            .with(layer)
+    }
+
+    /// Returns the current contents of this terminal, with trailing whitespace trimmed.
+    ///
+    /// Note, progress bars are redrawn at most [`TickSettings::term_draw_hz`](crate::TickSettings)
+    /// times a second, so this may not yet reflect the latest progress bar state. See
+    /// [`Self::wait_until_settled`].
+    pub fn contents(&self) -> String {
+        self.term.contents()
+    }
+
+    /// Waits until the contents of this terminal stop changing, then returns them.
+    ///
+    /// Gives up after a few seconds and returns the contents at that point, e.g. if a spinner
+    /// keeps redrawing the terminal.
+    pub fn wait_until_settled(&self) -> String {
+        let deadline = Instant::now() + SETTLE_TIMEOUT;
+        let mut contents = self.contents();
+
+        loop {
+            thread::sleep(SETTLE_DURATION);
+
+            let next_contents = self.contents();
+            if next_contents == contents || Instant::now() >= deadline {
+                return next_contents;
+            }
+
+            contents = next_contents;
+        }
+    }
+
+    /// Waits until the contents of this terminal satisfy `predicate` or `timeout` has passed, then
+    /// returns them.
+    pub fn wait_until(&self, predicate: impl Fn(&str) -> bool, timeout: Duration) -> String {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let contents = self.contents();
+            if predicate(&contents) || Instant::now() >= deadline {
+                return contents;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Returns the underlying [`InMemoryTerm`].
+    pub fn term(&self) -> &InMemoryTerm {
+        &self.term
+    }
+}
+
+/// A writer to a [`TestTerminal`], see [`TestTerminal::writer`].
+#[derive(Clone)]
+pub struct InMemoryTermWriter {
+    pub(crate) progress_bars: Option<MultiProgress>,
+    pub(crate) rendering_enabled: Arc<AtomicBool>,
+    pub(crate) term: InMemoryTerm,
+}
+
+impl InMemoryTermWriter {
+    fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self.progress_bars {
+            Some(ref pb) if self.rendering_enabled.load(Ordering::Acquire) => pb.suspend(f),
+            _ => f(),
+        }
+    }
+}
+
+impl io::Write for InMemoryTermWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.suspend(|| self.term.write_str(s))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.suspend(|| self.term.flush())
+    }
+}
+
+impl<'a> MakeWriter<'a> for InMemoryTermWriter {
+    type Writer = InMemoryTermWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// A [`Clock`] that only moves forward when told to, to be passed to
+/// [`IndicatifLayer::with_clock`] for deterministic elapsed times and ETAs.
+///
+/// Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves this clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
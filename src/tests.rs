@@ -1,62 +1,44 @@
 use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
 use std::thread;
 use std::time::Duration;
 
 use indicatif::InMemoryTerm;
-use indicatif::MultiProgress;
-use indicatif::ProgressDrawTarget;
 use indicatif::ProgressStyle;
 use indicatif::TermLike;
 use tracing::info;
 use tracing::info_span;
 use tracing_core::Subscriber;
-use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::fmt::format::DefaultFields;
+use tracing_subscriber::layer::Layer;
 use tracing_subscriber::layer::SubscriberExt;
 
+use crate::ChildProgress;
+use crate::DrawTarget;
 use crate::IndicatifLayer;
+use crate::OutputMode;
+use crate::ProgressBarOrder;
+use crate::StyleMatcher;
 use crate::TickSettings;
+use crate::disable_tracing_indicatif;
+use crate::enable_tracing_indicatif;
+use crate::filter::Directives;
+use crate::filter::IndicatifFilter;
+use crate::filter::hide_indicatif_progress_events;
 use crate::filter::hide_indicatif_span_fields;
+use crate::handle::get_indicatif_handle;
 use crate::span_ext::IndicatifSpanExt;
 use crate::suspend_tracing_indicatif;
-
-#[derive(Clone)]
-struct InMemoryTermWriter {
-    progress_bars: Option<MultiProgress>,
-    term: InMemoryTerm,
-}
-
-impl io::Write for InMemoryTermWriter {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if let Some(ref pb) = self.progress_bars {
-            pb.suspend(|| self.term.write_str(std::str::from_utf8(buf).unwrap()))?;
-        } else {
-            self.term.write_str(std::str::from_utf8(buf).unwrap())?;
-        }
-
-        Ok(buf.len())
-    }
-
-    fn flush(&mut self) -> io::Result<()> {
-        if let Some(ref pb) = self.progress_bars {
-            pb.suspend(|| self.term.flush())
-        } else {
-            self.term.flush()
-        }
-    }
-}
-
-impl<'a> MakeWriter<'a> for InMemoryTermWriter {
-    type Writer = InMemoryTermWriter;
-
-    fn make_writer(&'a self) -> Self::Writer {
-        self.clone()
-    }
-}
+use crate::testing::InMemoryTermWriter;
+use crate::testing::ManualClock;
+use crate::testing::TestTerminal;
 
 struct HelpersConfig {
     show_footer: bool,
     enable_steady_tick: bool,
+    output_mode: OutputMode,
 }
 
 impl Default for HelpersConfig {
@@ -64,11 +46,14 @@ impl Default for HelpersConfig {
         Self {
             show_footer: true,
             enable_steady_tick: false,
+            output_mode: OutputMode::ProgressBars,
         }
     }
 }
 
-fn make_helpers(config: HelpersConfig) -> (impl Subscriber, InMemoryTerm) {
+fn make_helpers(config: HelpersConfig) -> (impl Subscriber, TestTerminal) {
+    let term = TestTerminal::new(10, 100);
+
     let indicatif_layer = IndicatifLayer::new()
         .with_max_progress_bars(
             5,
@@ -92,31 +77,13 @@ fn make_helpers(config: HelpersConfig) -> (impl Subscriber, InMemoryTerm) {
                 None
             },
             footer_tick_interval: None,
+            text_line_interval: Duration::ZERO,
+            heartbeat_interval: Duration::from_millis(100),
             ..Default::default()
-        });
-
-    let term = InMemoryTerm::new(10, 100);
-
-    let mp = indicatif_layer.pb_manager.lock().unwrap().mp.clone();
-
-    mp.set_draw_target(ProgressDrawTarget::term_like(Box::new(term.clone())));
-
-    let writer = InMemoryTermWriter {
-        progress_bars: Some(mp),
-        term: term.clone(),
-    };
+        })
+        .with_output_mode(config.output_mode);
 
-    (
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::fmt::layer()
-                    .with_ansi(false)
-                    .without_time()
-                    .with_writer(writer),
-            )
-            .with(indicatif_layer),
-        term,
-    )
+    (term.subscriber(indicatif_layer), term)
 }
 
 #[test]
@@ -800,6 +767,7 @@ fn test_span_ext_no_effect_when_layer_not_added() {
 
     let writer = InMemoryTermWriter {
         progress_bars: None,
+        rendering_enabled: Arc::new(AtomicBool::new(true)),
         term: term.clone(),
     };
 
@@ -847,7 +815,7 @@ foo{}
             .trim()
         );
 
-        let _ = suspend_tracing_indicatif(|| term.write_line("hello world"));
+        let _ = suspend_tracing_indicatif(|| term.term().write_line("hello world"));
 
         assert_eq!(
             term.contents()
@@ -862,7 +830,7 @@ foo{}
             .trim()
         );
 
-        let _ = suspend_tracing_indicatif(|| term.write_line("this is a test"));
+        let _ = suspend_tracing_indicatif(|| term.term().write_line("this is a test"));
 
         assert_eq!(
             term.contents()
@@ -909,6 +877,7 @@ hello world
 #[test]
 fn test_with_span_field_formatter() {
     let indicatif_layer = IndicatifLayer::new()
+        .with_output_mode(OutputMode::ProgressBars)
         .with_span_field_formatter(hide_indicatif_span_fields(DefaultFields::new()));
 
     let subscriber = tracing_subscriber::registry()
@@ -975,6 +944,1780 @@ fn test_pb_eta() {
     });
 }
 
+#[test]
+fn test_hidden_draw_target() {
+    let buffer = SharedBuffer::default();
+
+    let indicatif_layer = IndicatifLayer::new()
+        .with_draw_target(DrawTarget::hidden())
+        .with_max_progress_bars(1, None)
+        .with_json_events(buffer.clone());
+
+    let subscriber = tracing_subscriber::registry().with(indicatif_layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span1 = info_span!("foo");
+        span1.pb_start();
+        let span2 = info_span!("bar");
+        span2.pb_start();
+
+        // Visible and pending progress bars should still be tracked correctly, despite every
+        // progress bar reporting itself as hidden.
+        drop(span1);
+        drop(span2);
+
+        let span3 = info_span!("baz");
+        span3.pb_start();
+
+        // The freed slot is taken right away, rather than the progress bar waiting as pending.
+        let contents = buffer.contents();
+        let events = contents
+            .lines()
+            .map(|line| line.split('"').nth(3).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                "created", "shown", "created", "pending", "cleared", "shown", "cleared", "created",
+                "shown"
+            ]
+        );
+        assert!(contents.lines().last().unwrap().contains(r#""name":"baz""#));
+    });
+}
+
+#[test]
+fn test_text_output_mode() {
+    let (subscriber, term) = make_helpers(HelpersConfig {
+        output_mode: OutputMode::Text,
+        ..Default::default()
+    });
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo", val = 3);
+        span.pb_set_length(100);
+        span.pb_start();
+        span.pb_set_position(40);
+        // Unchanged progress should not emit a line.
+        span.pb_set_position(40);
+        span.pb_set_message("hello");
+        drop(span);
+
+        assert_eq!(
+            term.contents(),
+            r#"
+[foo{val=3}] started
+[foo{val=3}] 40/100 (0s)
+[foo{val=3}] 40/100 hello (0s)
+[foo{val=3}] finished (0s)
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_text_output_mode_clock() {
+    let term = TestTerminal::new(10, 100);
+    let clock = ManualClock::new();
+
+    let subscriber = term.subscriber(
+        IndicatifLayer::new()
+            .with_tick_settings(TickSettings {
+                text_line_interval: Duration::from_secs(1),
+                ..Default::default()
+            })
+            .with_output_mode(OutputMode::Text)
+            .with_clock(clock.clone()),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo");
+        span.pb_set_length(10);
+        span.pb_start();
+
+        // Too soon after the span started.
+        span.pb_set_position(1);
+        clock.advance(Duration::from_secs(1));
+        span.pb_set_position(2);
+        // Too soon after the previous line.
+        span.pb_set_position(3);
+        clock.advance(Duration::from_secs(2));
+        span.pb_set_position(4);
+        drop(span);
+
+        assert_eq!(
+            term.contents(),
+            r#"
+[foo{}] started
+[foo{}] 2/10 (1s)
+[foo{}] 4/10 (3s)
+[foo{}] finished (3s)
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_heartbeat_output_mode() {
+    let (subscriber, term) = make_helpers(HelpersConfig {
+        output_mode: OutputMode::Heartbeat,
+        ..Default::default()
+    });
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo", val = 3);
+        span.pb_set_length(100);
+        span.pb_set_position(40);
+        span.pb_start();
+        let child_span = info_span!(parent: &span, "child");
+        child_span.pb_start();
+
+        let other_spans: Vec<_> = (0..5).map(|i| info_span!("bar", i)).collect();
+        for span in &other_spans {
+            span.pb_start();
+        }
+
+        // Nothing is printed until the first heartbeat.
+        assert_eq!(term.contents(), "");
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            term.contents(),
+            r#"
+[progress] 5 active, 2 pending
+foo{val=3} 40/100 (0s)
+--> child{} (0s)
+bar{i=0} (0s)
+bar{i=1} (0s)
+bar{i=2} (0s)
+            "#
+            .trim()
+        );
+    });
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn test_json_events() {
+    let buffer = SharedBuffer::default();
+
+    let indicatif_layer = IndicatifLayer::new()
+        .with_draw_target(DrawTarget::hidden())
+        .with_max_progress_bars(1, None)
+        .with_json_events(buffer.clone());
+
+    let subscriber = tracing_subscriber::registry().with(indicatif_layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span1 = info_span!("foo", val = 3);
+        span1.pb_set_length(10);
+        span1.pb_start();
+        span1.pb_inc(4);
+
+        let span2 = info_span!("bar");
+        span2.pb_start();
+        span2.pb_set_message("hello \"world\"");
+        span2.pb_set_finish_message("done");
+
+        drop(span1);
+        drop(span2);
+    });
+
+    let contents = buffer.contents();
+    let events = contents
+        .lines()
+        .map(|line| {
+            assert!(line.starts_with(r#"{"event":""#) && line.ends_with('}'));
+            line.split('"').nth(3).unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        events,
+        [
+            "created", "shown", "position", "created", "pending", "message", "cleared", "shown",
+            "finished"
+        ]
+    );
+
+    let position_event = contents.lines().nth(2).unwrap();
+    assert!(position_event.contains(r#""parent_span_id":null,"name":"foo","fields":"val=3","#));
+    assert!(position_event.contains(r#""pos":4,"len":10,"message":""}"#));
+
+    let message_event = contents.lines().nth(5).unwrap();
+    assert!(message_event.ends_with(r#""message":"hello \"world\""}"#));
+
+    let finished_event = contents.lines().last().unwrap();
+    assert!(finished_event.contains(r#""name":"bar","fields":"","#));
+    assert!(finished_event.ends_with(r#""pos":0,"len":null,"message":"done"}"#));
+}
+
+#[test]
+fn test_json_events_pushed_back() {
+    let buffer = SharedBuffer::default();
+
+    let indicatif_layer = IndicatifLayer::new()
+        .with_draw_target(DrawTarget::hidden())
+        .with_max_progress_bars(1, None)
+        .with_json_events(buffer.clone());
+
+    let subscriber = tracing_subscriber::registry().with(indicatif_layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let worker = info_span!("worker");
+        worker.pb_start();
+
+        // Pushes back `worker`, which is shown again once `overall` closes.
+        let overall = info_span!("overall", indicatif.pb_pinned = true);
+        overall.pb_start();
+        drop(overall);
+    });
+
+    let contents = buffer.contents();
+    let events = contents
+        .lines()
+        .map(|line| {
+            let mut parts = line.split('"');
+            let event = parts.nth(3).unwrap();
+            let name = parts.find(|part| *part == "worker" || *part == "overall");
+            (event, name.unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        events,
+        [
+            ("created", "worker"),
+            ("shown", "worker"),
+            ("created", "overall"),
+            ("pending", "worker"),
+            ("shown", "overall"),
+            ("cleared", "overall"),
+            ("shown", "worker"),
+            ("cleared", "worker"),
+        ]
+    );
+}
+
+#[test]
+fn test_manual_clock() {
+    let term = TestTerminal::new(10, 100);
+    let clock = ManualClock::new();
+
+    let subscriber = term.subscriber(
+        IndicatifLayer::new()
+            .with_progress_style(
+                ProgressStyle::with_template("{span_name} {elapsed_precise} {eta_precise}")
+                    .unwrap(),
+            )
+            .with_clock(clock.clone()),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo");
+        span.pb_set_length(10);
+        span.pb_start();
+
+        clock.advance(Duration::from_secs(5));
+        span.pb_inc(5);
+        span.pb_tick();
+
+        assert_eq!(span.pb_elapsed(), Duration::from_secs(5));
+        assert_eq!(span.pb_eta(), Duration::from_secs(5));
+        assert_eq!(term.wait_until_settled(), "foo 00:00:05 00:00:05");
+
+        clock.advance(Duration::from_secs(10));
+        span.pb_inc(1);
+        span.pb_tick();
+
+        assert_eq!(span.pb_elapsed(), Duration::from_secs(15));
+        assert_eq!(span.pb_eta(), Duration::from_secs(10));
+        assert_eq!(term.wait_until_settled(), "foo 00:00:15 00:00:10");
+    });
+}
+
+#[test]
+fn test_handle() {
+    // Restyling redraws every progress bar at once, so rely on steady ticks to catch up with draws
+    // dropped by indicatif's rate limiting.
+    let (subscriber, term) = make_helpers(HelpersConfig {
+        enable_steady_tick: true,
+        ..Default::default()
+    });
+
+    tracing::subscriber::with_default(subscriber, || {
+        let handle = get_indicatif_handle().unwrap();
+
+        let spans = (1..=6)
+            .map(|i| {
+                let span = info_span!("outer", val = i);
+                span.pb_start();
+                span
+            })
+            .collect::<Vec<_>>();
+
+        let child = info_span!(parent: &spans[0], "inner");
+        child.pb_start();
+
+        let custom = info_span!("custom");
+        custom.pb_set_style(&ProgressStyle::with_template("custom style").unwrap());
+        custom.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+outer{val=1}
+outer{val=2}
+outer{val=3}
+outer{val=4}
+outer{val=5}
+...and 3 more not shown above.
+            "#
+            .trim()
+        );
+
+        handle.set_progress_style(
+            ProgressStyle::with_template("{span_child_prefix}{span_name} {span_fields}").unwrap(),
+        );
+        handle.set_span_child_prefix_symbol("* ");
+        handle.set_max_progress_bars(
+            8,
+            Some(ProgressStyle::with_template("{pending_progress_bars} hidden").unwrap()),
+        );
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+outer val=1
+--* inner
+outer val=2
+outer val=3
+outer val=4
+outer val=5
+outer val=6
+custom style
+            "#
+            .trim()
+        );
+
+        handle.set_max_progress_bars(
+            0,
+            Some(ProgressStyle::with_template("{pending_progress_bars} hidden").unwrap()),
+        );
+        let _late = info_span!("late").entered();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+outer val=1
+--* inner
+outer val=2
+outer val=3
+outer val=4
+outer val=5
+outer val=6
+custom style
+1 hidden
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_disable_rendering() {
+    let (subscriber, term) = make_helpers(HelpersConfig::default());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo");
+        span.pb_set_style(&ProgressStyle::with_template("{span_name} {pos}/{len}").unwrap());
+        span.pb_set_length(10);
+        span.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "foo 0/10");
+
+        disable_tracing_indicatif();
+        assert!(!get_indicatif_handle().unwrap().is_rendering_enabled());
+        assert_eq!(term.wait_until_settled(), "");
+
+        span.pb_inc(3);
+        info!("hello world");
+        assert_eq!(
+            term.wait_until_settled().trim(),
+            "INFO tracing_indicatif::tests: hello world"
+        );
+
+        enable_tracing_indicatif();
+        assert_eq!(
+            term.wait_until_settled()
+                .lines()
+                .map(|line| line.trim())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            r#"
+INFO tracing_indicatif::tests: hello world
+foo 3/10
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_record_progress_fields() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = term.subscriber(
+        IndicatifLayer::new()
+            .with_span_field_formatter(hide_indicatif_span_fields(DefaultFields::new()))
+            .with_progress_style(
+                ProgressStyle::with_template("{span_name}{{{span_fields}}} {pos}/{len} {msg}")
+                    .unwrap(),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!(
+            "foo",
+            val = 3,
+            indicatif.pb_len = 10,
+            indicatif.pb_pos = tracing::field::Empty,
+            indicatif.pb_inc = tracing::field::Empty,
+            indicatif.pb_msg = tracing::field::Empty,
+        );
+        span.record("indicatif.pb_inc", 2);
+        span.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "foo{val=3} 2/10");
+
+        span.record("indicatif.pb_pos", 5);
+        span.record("indicatif.pb_inc", 1);
+        span.record("indicatif.pb_msg", "working");
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "foo{val=3} 6/10 working");
+
+        span.record("indicatif.pb_len", 20);
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "foo{val=3} 6/20 working");
+    });
+}
+
+#[test]
+fn test_record_progress_fields_not_merged() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = term.subscriber(
+        IndicatifLayer::new()
+            .with_span_field_formatter(DefaultFields::new())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_name}{{{span_fields}}} {pos}/{len}").unwrap(),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!(
+            "dl",
+            indicatif.pb_len = 100,
+            indicatif.pb_inc = tracing::field::Empty,
+            file = tracing::field::Empty,
+        );
+        span.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "dl{indicatif.pb_len=100} 0/100");
+
+        for _ in 0..5 {
+            span.record("indicatif.pb_inc", 1);
+        }
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "dl{indicatif.pb_len=100} 5/100");
+
+        // Other fields recorded along with progress fields are still merged.
+        let fields = span.metadata().unwrap().fields();
+        let inc = fields.field("indicatif.pb_inc").unwrap();
+        let file = fields.field("file").unwrap();
+        span.record_all(&fields.value_set(&[
+            (&inc, Some(&1 as &dyn tracing::Value)),
+            (&file, Some(&"foo.txt" as &dyn tracing::Value)),
+        ]));
+        span.pb_tick();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            "dl{indicatif.pb_len=100 file=\"foo.txt\"} 6/100"
+        );
+    });
+}
+
+#[test]
+fn test_record_span_fields() {
+    let (subscriber, term) = make_helpers(HelpersConfig::default());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("download", file = tracing::field::Empty, attempt = 1);
+        span.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "download{attempt=1}");
+
+        span.record("file", "foo.txt");
+        assert_eq!(
+            term.wait_until_settled(),
+            "download{attempt=1 file=\"foo.txt\"}"
+        );
+
+        // Recorded before the progress bar is created.
+        let child = info_span!(parent: &span, "unpack", file = tracing::field::Empty);
+        child.record("file", "bar.txt");
+        child.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+download{attempt=1 file="foo.txt"}
+--> unpack{file="bar.txt"}
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_progress_events() {
+    let term = TestTerminal::new(10, 100);
+    let logs = SharedBuffer::default();
+    let indicatif_layer = IndicatifLayer::new()
+        .with_draw_target(term.draw_target())
+        .with_progress_style(
+            ProgressStyle::with_template("{span_name} {pos}/{len} {msg}").unwrap(),
+        );
+
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .without_time()
+                .with_writer({
+                    let logs = logs.clone();
+                    move || logs.clone()
+                })
+                .with_filter(hide_indicatif_progress_events()),
+        )
+        .with(indicatif_layer.with_filter(IndicatifFilter::new(false)));
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo", indicatif.pb_show = tracing::field::Empty);
+        span.pb_start();
+
+        info!(parent: &span, indicatif.pb_len = 10);
+        info!(parent: &span, "not progress");
+
+        let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    let _enter = span.enter();
+                    // Reported to the closest ancestor with a progress bar.
+                    let _child = info_span!("child").entered();
+
+                    info!(indicatif.pb_inc = 3, indicatif.pb_msg = "chunk");
+                    info!(indicatif.pb_inc = 2, "processed chunk");
+                })
+            });
+        });
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "foo 5/10 chunk");
+    });
+
+    assert_eq!(
+        logs.contents(),
+        " INFO foo: tracing_indicatif::tests: not progress\n"
+    );
+}
+
+#[test]
+fn test_event_messages() {
+    let term = TestTerminal::new(10, 100);
+    // Without a `fmt` layer, so only the progress bars are drawn to the terminal.
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name} {msg}").unwrap(),
+            )
+            .with_event_messages(tracing::Level::INFO),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let parent = info_span!("parent");
+        parent.pb_start();
+        let child = info_span!(parent: &parent, "child");
+        child.pb_start();
+
+        info!(parent: &parent, "resolving");
+        info!(parent: &child, val = 3, "compiling");
+        tracing::debug!(parent: &child, "too verbose");
+        info!(parent: &child, val = 3);
+        parent.pb_tick();
+        child.pb_tick();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+parent resolving
+  ↳ child compiling
+            "#
+            .trim()
+        );
+    });
+
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name} {msg}").unwrap())
+            .with_event_messages(tracing::Level::DEBUG)
+            .with_event_message_formatter(|event| event.metadata().level().to_string()),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("foo");
+        span.pb_start();
+
+        tracing::debug!(parent: &span, "hello");
+        tracing::trace!(parent: &span, "world");
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "foo DEBUG");
+    });
+
+    // `IndicatifFilter` lets events through that do not report progress if configured to.
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name} {msg}").unwrap())
+            .with_event_messages(tracing::Level::DEBUG)
+            .with_filter(IndicatifFilter::new(true).with_event_level(tracing::Level::INFO)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = info_span!("work");
+        span.pb_start();
+
+        info!(parent: &span, "doing step 1");
+        tracing::debug!(parent: &span, "filtered out");
+        span.pb_tick();
+
+        assert_eq!(term.wait_until_settled(), "work doing step 1");
+    });
+}
+
+#[test]
+fn test_log_tail() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(3, None)
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_log_tail(tracing::Level::INFO, 2),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let foo = info_span!("foo");
+        foo.pb_start();
+
+        info!(parent: &foo, "one");
+        info!(parent: &foo, "two");
+        tracing::debug!(parent: &foo, "too verbose");
+        info!(parent: &foo, "three");
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+foo
+  two
+  three
+            "#
+            .trim()
+        );
+
+        // The oldest line makes room for another progress bar.
+        let bar = info_span!("bar");
+        bar.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+foo
+  three
+bar
+            "#
+            .trim()
+        );
+
+        // There is no room for lines of `bar`, but they are printed once it fails. The hidden line
+        // of `foo` takes the freed slot.
+        info!(parent: &bar, "building");
+        tracing::error!(parent: &bar, "boom");
+        drop(bar);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+building
+boom
+foo
+  two
+  three
+            "#
+            .trim()
+        );
+
+        drop(foo);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+building
+boom
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_log_tail_flush_disabled() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_log_tail(tracing::Level::INFO, 2)
+            .with_log_tail_flush(false),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let foo = info_span!("foo");
+        foo.pb_start();
+
+        tracing::error!(parent: &foo, "boom");
+
+        assert_eq!(term.wait_until_settled(), "foo\n  boom");
+
+        // The line is usually logged by a `fmt` layer already.
+        drop(foo);
+
+        assert_eq!(term.wait_until_settled(), "");
+    });
+}
+
+#[test]
+fn test_filter_field_values() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name} {pos}").unwrap(),
+            )
+            .with_filter(IndicatifFilter::new(false).with_field_values(true)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let verbose = false;
+
+        let root = info_span!("root", indicatif.pb_show = true);
+        root.pb_start();
+        let _root_enter = root.enter();
+
+        let quiet = info_span!("quiet", indicatif.pb_show = verbose);
+        quiet.pb_start();
+        let _quiet_enter = quiet.enter();
+
+        // Re-parented to `root`, as `quiet` has no progress bar.
+        let shown = info_span!("shown", indicatif.pb_hide = false);
+        shown.pb_start();
+        let hidden = info_span!("hidden", indicatif.pb_hide = true);
+        hidden.pb_start();
+        let empty = info_span!("empty", indicatif.pb_show = tracing::field::Empty);
+        empty.pb_start();
+        let default = info_span!("default");
+        default.pb_start();
+
+        // Reported to `root`.
+        info!(parent: &quiet, indicatif.pb_inc = 3);
+        root.pb_tick();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+root 3
+  ↳ shown 0
+  ↳ empty 0
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_filter_directives() {
+    let term = TestTerminal::new(10, 100);
+    let directives: Directives = "info, myapp::db=debug, myapp::db[query]=off, myapp::net=warn"
+        .parse()
+        .unwrap();
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(10, None)
+            .with_filter(IndicatifFilter::new(false).with_directives(directives)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let spans = [
+            info_span!("default"),
+            tracing::debug_span!("too_verbose"),
+            tracing::debug_span!(target: "myapp::db", "connect"),
+            info_span!(target: "myapp::db", "query"),
+            info_span!(target: "myapp::net", "fetch"),
+            info_span!(
+                target: "myapp::net",
+                "forced",
+                indicatif.pb_show = tracing::field::Empty
+            ),
+            tracing::warn_span!(target: "myapp::net", "retry"),
+        ];
+
+        for span in &spans {
+            span.pb_start();
+        }
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+default
+connect
+forced
+retry
+            "#
+            .trim()
+        );
+    });
+
+    assert_eq!(
+        Directives::try_new("myapp[query{id=1}]=info")
+            .unwrap_err()
+            .to_string(),
+        "invalid directive `myapp[query{id=1}]=info`: field filters are not supported"
+    );
+    assert!(Directives::try_new("myapp=loud").is_err());
+    assert!(Directives::try_new("myapp[query=info").is_err());
+}
+
+#[test]
+fn test_show_delay() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(
+                1,
+                Some(
+                    ProgressStyle::with_template(
+                        "...and {pending_progress_bars} more not shown above.",
+                    )
+                    .unwrap(),
+                ),
+            )
+            .with_show_delay(Duration::from_secs(1)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        // Closed before their delay passed, so neither drawn nor queued.
+        for _ in 0..5 {
+            info_span!("quick").pb_start();
+        }
+
+        let slow = info_span!("slow");
+        slow.pb_start();
+        let eager = info_span!("eager");
+        eager.pb_set_show_delay(Duration::ZERO);
+        eager.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "eager");
+
+        assert_eq!(
+            term.wait_until(|contents| contents.contains("more"), Duration::from_secs(5)),
+            r#"
+eager
+...and 1 more not shown above.
+            "#
+            .trim()
+        );
+
+        drop(eager);
+
+        assert_eq!(term.wait_until_settled(), "slow");
+    });
+}
+
+#[test]
+fn test_show_delay_children() {
+    let (subscriber, term) = make_helpers(HelpersConfig::default());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let parent = info_span!("parent");
+        parent.pb_set_show_delay(Duration::from_millis(500));
+        parent.pb_start();
+
+        // Shown along with its parent, without counting as pending meanwhile.
+        let child = info_span!(parent: &parent, "child");
+        child.pb_set_show_delay(Duration::ZERO);
+        child.pb_start();
+
+        assert_eq!(term.wait_until_settled(), "");
+
+        assert_eq!(
+            term.wait_until(|contents| !contents.is_empty(), Duration::from_secs(5)),
+            r#"
+parent{}
+--> child{}
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_show_delay_restyle() {
+    let (subscriber, term) = make_helpers(HelpersConfig::default());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let parent = info_span!("parent");
+        parent.pb_set_show_delay(Duration::from_millis(500));
+        parent.pb_start();
+        let child = info_span!(parent: &parent, "child");
+        child.pb_start();
+
+        // Restyled while still within their show delay.
+        let handle = get_indicatif_handle().unwrap();
+        handle.set_progress_style(
+            ProgressStyle::with_template("{span_child_prefix}new {span_name}").unwrap(),
+        );
+        handle.set_span_child_prefix_symbol("* ");
+
+        assert_eq!(
+            term.wait_until(|contents| !contents.is_empty(), Duration::from_secs(5)),
+            r#"
+new parent
+--* new child
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_show_delay_text_output() {
+    let (subscriber, term) = make_helpers(HelpersConfig {
+        output_mode: OutputMode::Text,
+        ..Default::default()
+    });
+
+    tracing::subscriber::with_default(subscriber, || {
+        // Closed before its delay passed, so nothing is printed.
+        let quick = info_span!("quick");
+        quick.pb_set_show_delay(Duration::from_secs(1));
+        quick.pb_start();
+        drop(quick);
+
+        let eager = info_span!("eager");
+        eager.pb_start();
+        drop(eager);
+
+        assert_eq!(
+            term.contents(),
+            r#"
+[eager{}] started
+[eager{}] finished (0s)
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_min_display_time() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(
+                1,
+                Some(
+                    ProgressStyle::with_template(
+                        "...and {pending_progress_bars} more not shown above.",
+                    )
+                    .unwrap(),
+                ),
+            )
+            .with_min_display_time(
+                Duration::from_secs(1),
+                Some(ProgressStyle::with_template("{span_name} done").unwrap()),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let foo = info_span!("foo");
+        foo.pb_start();
+        let bar = info_span!("bar");
+        bar.pb_start();
+        drop(foo);
+
+        // `foo` keeps its slot until it was shown long enough.
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+foo done
+...and 1 more not shown above.
+            "#
+            .trim()
+        );
+
+        assert_eq!(
+            term.wait_until(|contents| contents == "bar", Duration::from_secs(5)),
+            "bar"
+        );
+    });
+}
+
+#[test]
+fn test_min_display_time_order() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_progress_bar_order(ProgressBarOrder::Field("rank"))
+            .with_min_display_time(
+                Duration::from_secs(1),
+                Some(ProgressStyle::with_template("{span_name} done").unwrap()),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let two = info_span!("two", rank = 2);
+        two.pb_start();
+        let three = info_span!("three", rank = 3);
+        three.pb_start();
+        drop(two);
+
+        // New progress bars are placed around the one kept shown.
+        let one = info_span!("one", rank = 1);
+        one.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+one
+two done
+three
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_style_rules() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(10, None)
+            .with_style_rule(
+                StyleMatcher::name("fetch"),
+                ProgressStyle::with_template("[name] {span_name}").unwrap(),
+            )
+            .with_style_rule(
+                StyleMatcher::pb_style("build"),
+                ProgressStyle::with_template("[pb_style] {span_name}").unwrap(),
+            )
+            .with_style_rule(
+                StyleMatcher::target_prefix("myapp::db"),
+                ProgressStyle::with_template("[target] {span_name}").unwrap(),
+            )
+            .with_style_rule(
+                StyleMatcher::level(tracing::Level::DEBUG),
+                ProgressStyle::with_template("[level] {span_name}").unwrap(),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let spans = [
+            info_span!("plain"),
+            // The first matching rule wins.
+            tracing::debug_span!("fetch"),
+            info_span!("compile", indicatif.pb_style = "build"),
+            info_span!(target: "myapp::db::pool", "query"),
+            tracing::debug_span!("verbose"),
+            info_span!("custom", indicatif.pb_style = "build"),
+        ];
+
+        spans[5].pb_set_style(&ProgressStyle::with_template("[custom] {span_name}").unwrap());
+
+        for span in &spans {
+            span.pb_start();
+        }
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+plain
+[name] fetch
+[pb_style] compile
+[target] query
+[level] verbose
+[custom] custom
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_progress_bar_order() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_max_progress_bars(4, None),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let root = info_span!("root");
+        root.pb_start();
+
+        let a = info_span!(parent: &root, "a");
+        let b = info_span!(parent: &root, "b");
+        let c = info_span!(parent: &root, "c");
+
+        // Children are shown in the order they were created, not started.
+        c.pb_start();
+        b.pb_start();
+        a.pb_start();
+
+        // Pending until `b` finishes, but then shown below `a` rather than at the bottom.
+        let a_child = info_span!(parent: &a, "a.child");
+        a_child.pb_start();
+        drop(b);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+root
+  ↳ a
+    ↳ a.child
+  ↳ c
+            "#
+            .trim()
+        );
+    });
+
+    // Top level progress bars are drawn in the order they are shown, unless an order is set.
+    for (order, expected) in [(None, "z\nx"), (Some(ProgressBarOrder::Creation), "x\nz")] {
+        let term = TestTerminal::new(10, 100);
+        let mut indicatif_layer = IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(2, None);
+
+        if let Some(order) = order {
+            indicatif_layer = indicatif_layer.with_progress_bar_order(order);
+        }
+
+        let subscriber = tracing_subscriber::registry().with(indicatif_layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let x = info_span!("x");
+            let y = info_span!("y");
+            let z = info_span!("z");
+
+            y.pb_start();
+            z.pb_start();
+            x.pb_start();
+            drop(y);
+
+            assert_eq!(term.wait_until_settled(), expected);
+        });
+    }
+
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_progress_bar_order(ProgressBarOrder::Field("rank")),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let root = info_span!("root");
+        root.pb_start();
+
+        let spans = [
+            info_span!(parent: &root, "ten", rank = 10),
+            info_span!(parent: &root, "unranked"),
+            info_span!(parent: &root, "two", rank = 2),
+            info_span!(parent: &root, "one", rank = 1),
+        ];
+
+        for span in &spans {
+            span.pb_start();
+        }
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+root
+  ↳ one
+  ↳ two
+  ↳ ten
+  ↳ unranked
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_child_progress() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name} {pos}/{len}").unwrap())
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_child_progress(ChildProgress::Count),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let build = info_span!("build");
+        build.pb_start();
+
+        let units = (0..3)
+            .map(|_| info_span!(parent: &build, "build_sub_unit"))
+            .collect::<Vec<_>>();
+
+        let download = info_span!("download");
+        download.pb_set_child_progress(ChildProgress::Length);
+        download.pb_start();
+
+        let small = info_span!(parent: &download, "small", indicatif.pb_len = 10);
+        let large = info_span!(
+            parent: &download,
+            "large",
+            indicatif.pb_len = 10,
+            indicatif.pb_weight = 2
+        );
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+build 0/3
+download 0/30
+            "#
+            .trim()
+        );
+
+        let mut units = units.into_iter();
+        drop(units.next());
+        drop(units.next());
+
+        small.pb_inc(5);
+        drop(large);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+build 2/3
+download 25/30
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_priority() {
+    let term = TestTerminal::new(10, 100);
+    let layer = IndicatifLayer::new()
+        .with_draw_target(term.draw_target())
+        .with_progress_style(
+            ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+        )
+        .with_max_progress_bars(1, None)
+        .with_priority(|_, depth| -(depth as i64));
+    let handle = layer.get_handle();
+    let subscriber = tracing_subscriber::registry().with(layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let first = info_span!("first");
+        first.pb_start();
+
+        let cache_warming = info_span!("cache_warming", indicatif.pb_priority = -10);
+        cache_warming.pb_start();
+        let job = info_span!("job");
+        let sub_step = info_span!(parent: &job, "sub_step");
+        sub_step.pb_start();
+        let download = info_span!("download");
+        download.pb_set_priority(10);
+        download.pb_start();
+
+        drop(first);
+
+        assert_eq!(term.wait_until_settled(), "download");
+
+        drop(download);
+
+        assert_eq!(term.wait_until_settled(), "job");
+
+        // Deeper spans come after top level spans, but before spans with a lower priority.
+        handle.set_max_progress_bars(2, None);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+job
+  ↳ sub_step
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_pinned() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_max_progress_bars(
+                2,
+                Some(ProgressStyle::with_template("...and {pending_progress_bars} more").unwrap()),
+            )
+            .with_pinned(|metadata| metadata.name() == "overall"),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let worker1 = info_span!("worker1");
+        worker1.pb_start();
+        let worker2 = info_span!("worker2");
+        worker2.pb_start();
+
+        // Pushes back the most recently shown progress bar and stays at the top.
+        let overall = info_span!("overall");
+        overall.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+overall
+worker1
+...and 1 more
+            "#
+            .trim()
+        );
+
+        drop(worker1);
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+overall
+worker2
+            "#
+            .trim()
+        );
+
+        let status = info_span!("status", indicatif.pb_pinned = true);
+        status.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+overall
+status
+...and 1 more
+            "#
+            .trim()
+        );
+
+        // Shown beyond the maximum, as all shown progress bars are pinned.
+        let late = info_span!("late");
+        late.pb_set_pinned(true);
+        late.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+overall
+status
+late
+...and 1 more
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_pinned_evicts_leaf() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_max_progress_bars(
+                3,
+                Some(ProgressStyle::with_template("...and {pending_progress_bars} more").unwrap()),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let build = info_span!("build", indicatif.pb_priority = -1);
+        build.pb_start();
+        let compile = info_span!(parent: &build, "compile");
+        compile.pb_start();
+        let test = info_span!("test");
+        test.pb_start();
+
+        // `build` has the lowest priority, but would take `compile` along with it.
+        let status = info_span!("status", indicatif.pb_pinned = true);
+        status.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+status
+build
+  ↳ compile
+...and 1 more
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_pinned_keeps_lingering_children() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_max_progress_bars(
+                2,
+                Some(ProgressStyle::with_template("...and {pending_progress_bars} more").unwrap()),
+            )
+            .with_min_display_time(Duration::from_secs(5), None),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let parent = info_span!("parent");
+        parent.pb_start();
+        info_span!(parent: &parent, "child").pb_start();
+
+        // `parent` would leave its lingering child behind, so it is not pushed back.
+        let pinned = info_span!("pinned", indicatif.pb_pinned = true);
+        pinned.pb_start();
+
+        assert_eq!(
+            term.wait_until_settled(),
+            r#"
+pinned
+parent
+  ↳ child
+            "#
+            .trim()
+        );
+    });
+}
+
+#[test]
+fn test_rotation() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name} {pos}").unwrap())
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_max_progress_bars(2, None)
+            .with_rotation(Duration::from_millis(300)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let spans = [info_span!("a"), info_span!("b"), info_span!("c")];
+
+        for span in &spans {
+            span.pb_start();
+        }
+
+        assert_eq!(
+            term.wait_until(|contents| contents == "a 0\nb 0", Duration::from_secs(5)),
+            "a 0\nb 0"
+        );
+
+        // The progress bar shown the longest makes room, and is shown at the bottom again.
+        spans[0].pb_inc(5);
+
+        assert_eq!(
+            term.wait_until(|contents| contents == "b 0\nc 0", Duration::from_secs(5)),
+            "b 0\nc 0"
+        );
+        assert_eq!(
+            term.wait_until(|contents| contents == "c 0\na 5", Duration::from_secs(5)),
+            "c 0\na 5"
+        );
+    });
+}
+
+#[test]
+fn test_rotation_priorities() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(ProgressStyle::with_template("{span_name}").unwrap())
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_max_progress_bars(2, None)
+            .with_rotation(Duration::from_millis(300)),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let spans = [
+            info_span!("urgent", indicatif.pb_priority = 1),
+            info_span!("a"),
+            info_span!("b"),
+        ];
+
+        for span in &spans {
+            span.pb_start();
+        }
+
+        assert_eq!(term.wait_until_settled(), "urgent\na");
+
+        // Rotated out despite its priority, rather than being shown again right away.
+        assert_eq!(
+            term.wait_until(|contents| contents == "a\nb", Duration::from_secs(5)),
+            "a\nb"
+        );
+        assert_eq!(
+            term.wait_until(|contents| contents == "b\nurgent", Duration::from_secs(5)),
+            "b\nurgent"
+        );
+    });
+}
+
+#[test]
+fn test_max_child_progress_bars() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                footer_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_max_child_progress_bars(
+                2,
+                Some(
+                    ProgressStyle::with_template(
+                        "{span_child_prefix}…and {pending_progress_bars} more",
+                    )
+                    .unwrap(),
+                ),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let parent = info_span!("parent");
+        parent.pb_start();
+
+        let mut children = (0..4)
+            .map(|idx| info_span!(parent: &parent, "child", idx).entered().exit())
+            .collect::<Vec<_>>();
+
+        let other = info_span!("other");
+        other.pb_start();
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents.contains("2 more") && contents.contains("other"),
+                Duration::from_secs(5)
+            ),
+            "parent\n  ↳ child\n  ↳ child\n  ↳ …and 2 more\nother"
+        );
+
+        // Waiting children are shown as their siblings finish.
+        drop(children.remove(0));
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents.contains("1 more"),
+                Duration::from_secs(5)
+            ),
+            "parent\n  ↳ child\n  ↳ child\n  ↳ …and 1 more\nother"
+        );
+
+        // The footer is indented like the children.
+        get_indicatif_handle()
+            .unwrap()
+            .set_span_child_prefix_symbol("* ");
+
+        assert_eq!(
+            term.wait_until(|contents| contents.contains("* …"), Duration::from_secs(5)),
+            "parent\n  * child\n  * child\n  * …and 1 more\nother"
+        );
+
+        drop(children);
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents == "parent\nother",
+                Duration::from_secs(5)
+            ),
+            "parent\nother"
+        );
+    });
+}
+
+#[test]
+fn test_children_of_pending_pb() {
+    let term = TestTerminal::new(10, 100);
+    let subscriber = tracing_subscriber::registry().with(
+        IndicatifLayer::new()
+            .with_draw_target(term.draw_target())
+            .with_progress_style(
+                ProgressStyle::with_template("{span_child_prefix}{span_name}").unwrap(),
+            )
+            .with_tick_settings(TickSettings {
+                default_tick_interval: Some(Duration::from_millis(50)),
+                footer_tick_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            })
+            .with_max_progress_bars(
+                2,
+                Some(ProgressStyle::with_template("...and {pending_progress_bars} more").unwrap()),
+            ),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let a = info_span!("a");
+        a.pb_start();
+        let b = info_span!("b");
+        b.pb_start();
+        let c = info_span!("c");
+        c.pb_start();
+
+        // The children of a pending progress bar wait along with it, even if they come first.
+        let _children = (0..2)
+            .map(|idx| {
+                info_span!(parent: &c, "child", idx, indicatif.pb_priority = 1)
+                    .entered()
+                    .exit()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents.contains("3 more"),
+                Duration::from_secs(5)
+            ),
+            "a\nb\n...and 3 more"
+        );
+
+        // Its children are shown right after it.
+        drop(a);
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents.contains("2 more"),
+                Duration::from_secs(5)
+            ),
+            "b\nc\n...and 2 more"
+        );
+
+        drop(b);
+
+        assert_eq!(
+            term.wait_until(
+                |contents| contents.contains("1 more"),
+                Duration::from_secs(5)
+            ),
+            "c\n  ↳ child\n...and 1 more"
+        );
+    });
+}
+
 // These don't actually run anything, but exist to type check macros.
 #[allow(dead_code)]
 fn type_check_indicatif_println() {
@@ -0,0 +1,164 @@
+//! Plain text progress reporting, used when progress bars cannot be drawn in place (e.g. stderr is
+//! piped to a file).
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use indicatif::MultiProgress;
+use indicatif::ProgressBar;
+
+use crate::clock::Clock;
+use crate::pb_manager::DrawTarget;
+use crate::pb_manager::VisibleBar;
+
+/// Writes plain text progress lines to the draw target.
+pub(crate) struct TextOutput {
+    mp: MultiProgress,
+    draw_target: DrawTarget,
+    line_interval: Duration,
+    rendering_enabled: Arc<AtomicBool>,
+}
+
+impl TextOutput {
+    pub(crate) fn new(
+        mp: MultiProgress,
+        draw_target: DrawTarget,
+        line_interval: Duration,
+        rendering_enabled: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            mp,
+            draw_target,
+            line_interval,
+            rendering_enabled,
+        }
+    }
+
+    pub(crate) fn write_line(&self, line: &str) {
+        // Progress lines are dropped rather than buffered while rendering is disabled.
+        if self.rendering_enabled.load(Ordering::Acquire) {
+            self.draw_target.write_line(&self.mp, line);
+        }
+    }
+}
+
+/// Per-span state for plain text progress reporting.
+pub(crate) struct TextProgress {
+    output: Arc<TextOutput>,
+    // Drives the rate limiting of progress lines, see `IndicatifLayer::with_clock`.
+    clock: Arc<dyn Clock>,
+    last_line_at: Option<Instant>,
+    // The progress portion of the last line we emitted, used to skip lines that would not tell the
+    // user anything new.
+    last_progress: String,
+}
+
+impl TextProgress {
+    pub(crate) fn new(output: Arc<TextOutput>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            output,
+            clock,
+            last_line_at: None,
+            last_progress: String::new(),
+        }
+    }
+
+    pub(crate) fn started(&mut self, label: &str) {
+        self.output.write_line(&format!("[{label}] started"));
+        self.last_line_at = Some(self.clock.now());
+    }
+
+    /// Emits a progress line for this span, unless the progress has not changed or we emitted a
+    /// line for this span too recently.
+    pub(crate) fn progressed(&mut self, label: &str, pb: &ProgressBar, elapsed: Duration) {
+        let now = self.clock.now();
+
+        if self.last_line_at.is_some_and(|last_line_at| {
+            now.duration_since(last_line_at) < self.output.line_interval
+        }) {
+            return;
+        }
+
+        let progress = format_progress(pb);
+
+        if progress.is_empty() || progress == self.last_progress {
+            return;
+        }
+
+        self.output.write_line(&format!(
+            "[{label}] {progress} ({})",
+            format_elapsed(elapsed)
+        ));
+        self.last_line_at = Some(now);
+        self.last_progress = progress;
+    }
+
+    pub(crate) fn finished(
+        &mut self,
+        label: &str,
+        elapsed: Duration,
+        finish_message: Option<&str>,
+    ) {
+        self.output.write_line(&format!(
+            "[{label}] {} ({})",
+            finish_message.unwrap_or("finished"),
+            format_elapsed(elapsed)
+        ));
+    }
+}
+
+/// Formats a snapshot of the given progress bars, to be printed as heartbeat.
+pub(crate) fn format_heartbeat(
+    visible_bars: &[VisibleBar],
+    pending_progress_bars: usize,
+) -> Vec<String> {
+    let mut lines = Vec::with_capacity(visible_bars.len() + 1);
+
+    lines.push(format!(
+        "[progress] {} active, {} pending",
+        visible_bars.len(),
+        pending_progress_bars
+    ));
+
+    for bar in visible_bars {
+        let progress = format_progress(&bar.pb);
+        let elapsed = format_elapsed(bar.elapsed());
+
+        if progress.is_empty() {
+            lines.push(format!(
+                "{}{} ({elapsed})",
+                bar.span_child_prefix, bar.label
+            ));
+        } else {
+            lines.push(format!(
+                "{}{} {progress} ({elapsed})",
+                bar.span_child_prefix, bar.label
+            ));
+        }
+    }
+
+    lines
+}
+
+fn format_progress(pb: &ProgressBar) -> String {
+    let mut parts = Vec::new();
+
+    match pb.length() {
+        Some(len) => parts.push(format!("{}/{}", pb.position(), len)),
+        None if pb.position() > 0 => parts.push(pb.position().to_string()),
+        None => {}
+    }
+
+    let message = pb.message();
+    if !message.is_empty() {
+        parts.push(message);
+    }
+
+    parts.join(" ")
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("{}s", elapsed.as_secs())
+}
@@ -1,6 +1,9 @@
 //! Helpers to prevent progress bars from clobbering your console output.
 use std::io;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 
 use indicatif::MultiProgress;
 use tracing_subscriber::fmt::MakeWriter;
@@ -21,6 +24,8 @@ impl WriterTarget for Stderr {}
 /// tracing log entries are not clobbered.
 pub struct IndicatifWriter<Target = Stderr> {
     progress_bars: MultiProgress,
+    // If rendering is disabled, we write straight through instead of suspending the progress bars.
+    rendering_enabled: Arc<AtomicBool>,
     inner: PhantomData<Target>,
 }
 
@@ -35,17 +40,36 @@ where
     /// [`IndicatifLayer::get_stderr_writer`](crate::IndicatifLayer::get_stderr_writer) instead of
     /// this method.
     pub fn new(mp: MultiProgress) -> Self {
+        Self::with_rendering_enabled(mp, Arc::new(AtomicBool::new(true)))
+    }
+
+    pub(crate) fn with_rendering_enabled(
+        mp: MultiProgress,
+        rendering_enabled: Arc<AtomicBool>,
+    ) -> Self {
         Self {
             progress_bars: mp,
+            rendering_enabled,
             inner: PhantomData,
         }
     }
 }
 
+impl<T> IndicatifWriter<T> {
+    fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        if self.rendering_enabled.load(Ordering::Acquire) {
+            self.progress_bars.suspend(f)
+        } else {
+            f()
+        }
+    }
+}
+
 impl<T> Clone for IndicatifWriter<T> {
     fn clone(&self) -> Self {
         Self {
             progress_bars: self.progress_bars.clone(),
+            rendering_enabled: self.rendering_enabled.clone(),
             inner: self.inner,
         }
     }
@@ -53,47 +77,45 @@ impl<T> Clone for IndicatifWriter<T> {
 
 impl io::Write for IndicatifWriter<Stdout> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.progress_bars.suspend(|| io::stdout().write(buf))
+        self.suspend(|| io::stdout().write(buf))
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stdout().flush())
+        self.suspend(|| io::stdout().flush())
     }
 
     fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
-        self.progress_bars
-            .suspend(|| io::stdout().write_vectored(bufs))
+        self.suspend(|| io::stdout().write_vectored(bufs))
     }
 
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stdout().write_all(buf))
+        self.suspend(|| io::stdout().write_all(buf))
     }
 
     fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stdout().write_fmt(fmt))
+        self.suspend(|| io::stdout().write_fmt(fmt))
     }
 }
 
 impl io::Write for IndicatifWriter<Stderr> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.progress_bars.suspend(|| io::stderr().write(buf))
+        self.suspend(|| io::stderr().write(buf))
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stderr().flush())
+        self.suspend(|| io::stderr().flush())
     }
 
     fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
-        self.progress_bars
-            .suspend(|| io::stderr().write_vectored(bufs))
+        self.suspend(|| io::stderr().write_vectored(bufs))
     }
 
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stderr().write_all(buf))
+        self.suspend(|| io::stderr().write_all(buf))
     }
 
     fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> io::Result<()> {
-        self.progress_bars.suspend(|| io::stderr().write_fmt(fmt))
+        self.suspend(|| io::stderr().write_fmt(fmt))
     }
 }
 